@@ -0,0 +1,4 @@
+// Make sure `sqlx::migrate!()` picks up new migration files
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -1,15 +1,25 @@
 use warp::{
-    filters::{body::BodyDeserializeError, cors::CorsForbidden},
+    filters::body::BodyDeserializeError,
     reject::Reject,
     Rejection,
     Reply,
-    http::StatusCode,
+    http::{header::{ACCEPT, ACCEPT_ENCODING, RETRY_AFTER, UPGRADE, WWW_AUTHENTICATE}, HeaderValue, StatusCode},
 };
 
 use tracing::{event, Level, instrument};
 use reqwest::Error as ReqwestError;
 use reqwest_middleware::Error as MiddlewareReqwestError;
 
+tokio::task_local! {
+    /// Correlation id of the request currently being handled
+    pub static REQUEST_ID: String;
+}
+
+/// The id of the request being handled by the current task, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
 
 #[derive(Debug, Clone)]
 pub struct APILayerError {
@@ -25,6 +35,13 @@ impl std::fmt::Display for APILayerError {
 
 impl Reject for APILayerError {}
 
+/// A submitted field and the offensive words found in it
+#[derive(Debug, Clone)]
+pub struct OffensiveField {
+    pub field: String,
+    pub words: Vec<String>,
+}
+
 #[derive(Debug)]
 pub enum Error {
     ParseError(std::num::ParseIntError),
@@ -34,6 +51,39 @@ pub enum Error {
     MiddlewareReqwestAPIError(MiddlewareReqwestError),
     ClientError(APILayerError),
     ServerError(APILayerError),
+    /// The client exceeded its rate limit and may retry after this many seconds
+    TooManyRequests(u64),
+    /// A service needed to handle the request is down, and
+    /// won't be tried again for this many seconds
+    ServiceUnavailable(u64),
+    /// Submitted content was refused for containing offensive words
+    OffensiveContent(Vec<OffensiveField>),
+    /// The request body can't be deserialized from its content type
+    InvalidBody(String),
+    /// The request body has a content type other than the supported ones
+    UnsupportedMediaType(String, Vec<&'static str>),
+    /// None of the media types the response is available in are accepted
+    NotAcceptable(Vec<&'static str>),
+    /// The request body is larger than the route accepts, in bytes
+    PayloadTooLarge(u64),
+    /// The request body has a content encoding other than the supported ones
+    UnsupportedEncoding(String, Vec<&'static str>),
+    /// The `Idempotency-Key` header is empty or too long
+    InvalidIdempotencyKey,
+    /// A request with the same idempotency key is still being handled
+    IdempotencyKeyInUse,
+    /// The idempotency key was already used for a request with another body
+    IdempotencyKeyReused,
+    /// A cross-origin request from `origin` isn't allowed, for `reason`
+    CorsForbidden { origin: String, reason: String },
+    /// A WebSocket endpoint was requested without upgrading the connection
+    WebSocketRequired,
+    /// An admin route was requested without the admin token
+    Unauthorized,
+    /// No approved question has this id
+    QuestionNotFound(i32),
+    /// No approved answer has this id
+    AnswerNotFound(i32),
 }
 
 impl std::fmt::Display for Error {
@@ -49,6 +99,56 @@ impl std::fmt::Display for Error {
             Error::MiddlewareReqwestAPIError(err) => write!(f, "External API error: {}", err),
             Error::ClientError(err) => write!(f, "External Client error: {}", err),
             Error::ServerError(err) => write!(f, "External Server error: {}", err),
+            Error::TooManyRequests(secs) => write!(f, "Too many requests, retry in {}s", secs),
+            Error::ServiceUnavailable(secs) => {
+                write!(f, "Service temporarily unavailable, retry in {}s", secs)
+            }
+            Error::OffensiveContent(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|field| format!("{} ({})", field.field, field.words.join(", ")))
+                    .collect();
+                write!(f, "Content contains offensive words: {}", fields.join("; "))
+            }
+            Error::InvalidBody(err) => write!(f, "Request body deserialize error: {}", err),
+            Error::UnsupportedMediaType(content_type, supported) => write!(
+                f,
+                "Unsupported content type `{}`, expected one of: {}",
+                content_type,
+                supported.join(", ")
+            ),
+            Error::NotAcceptable(available) => write!(
+                f,
+                "Cannot respond in any of the accepted media types, available are: {}",
+                available.join(", ")
+            ),
+            Error::PayloadTooLarge(limit) => {
+                write!(f, "Request body exceeds the limit of {} bytes", limit)
+            }
+            Error::UnsupportedEncoding(encoding, supported) => write!(
+                f,
+                "Unsupported content encoding `{}`, expected one of: {}",
+                encoding,
+                supported.join(", ")
+            ),
+            Error::InvalidIdempotencyKey => {
+                write!(f, "Idempotency key must be between 1 and 255 characters")
+            }
+            Error::IdempotencyKeyInUse => {
+                write!(f, "A request with this idempotency key is still being processed")
+            }
+            Error::IdempotencyKeyReused => {
+                write!(f, "Idempotency key was already used for a different request")
+            }
+            Error::CorsForbidden { origin, reason } => {
+                write!(f, "CORS request from `{}` forbidden: {}", origin, reason)
+            }
+            Error::WebSocketRequired => {
+                write!(f, "This endpoint only accepts WebSocket connections")
+            }
+            Error::Unauthorized => write!(f, "Missing or invalid admin token"),
+            Error::QuestionNotFound(id) => write!(f, "Question {} not found", id),
+            Error::AnswerNotFound(id) => write!(f, "Answer {} not found", id),
         }
     }
 }
@@ -65,56 +165,133 @@ impl Reject for InvalidId {}
 pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
     if let Some(Error::DatabaseQueryError) = r.find() {
         event!(Level::ERROR, "Database query error");
-        Ok(warp::reply::with_status(
+        Ok(error_reply(
             Error::DatabaseQueryError.to_string(),
             StatusCode::UNPROCESSABLE_ENTITY,
         ))
     } else if let Some(crate::Error::ReqwestAPIError(e)) = r.find() {
         event!(Level::ERROR, "{}", e);
-        Ok(warp::reply::with_status(
+        Ok(error_reply(
             "Internal Server Error".to_string(),
             StatusCode::INTERNAL_SERVER_ERROR,
         ))
     } else if let Some(crate::Error::MiddlewareReqwestAPIError(e)) = r.find() {
         event!(Level::ERROR, "{}", e);
-        Ok(warp::reply::with_status(
+        Ok(error_reply(
             "Internal Server Error".to_string(),
             StatusCode::INTERNAL_SERVER_ERROR,
         ))
     } else if let Some(crate::Error::ClientError(e)) = r.find() {
         event!(Level::ERROR, "{}", e);
-        Ok(warp::reply::with_status(
+        Ok(error_reply(
             "Internal Server Error".to_string(),
             StatusCode::INTERNAL_SERVER_ERROR,
         ))
     } else if let Some(crate::Error::ServerError(e)) = r.find() {
         event!(Level::ERROR, "{}", e);
-        Ok(warp::reply::with_status(
+        Ok(error_reply(
             "Internal Server Error".to_string(),
-            StatusCode::INTERNAL_SERVER_ERROR,))
-    } else if let Some(error) = r.find::<Error>() {
-        event!(Level::ERROR, "{}", error);
-        Ok(warp::reply::with_status(
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ))
+    } else if let Some(crate::Error::TooManyRequests(secs)) = r.find() {
+        event!(Level::WARN, "Rate limit exceeded");
+        let mut res = error_reply(
+            Error::TooManyRequests(*secs).to_string(),
+            StatusCode::TOO_MANY_REQUESTS,
+        );
+        res.headers_mut().insert(RETRY_AFTER, HeaderValue::from(*secs));
+        Ok(res)
+    } else if let Some(crate::Error::ServiceUnavailable(secs)) = r.find() {
+        event!(Level::WARN, "Rejected request while a dependency is unavailable");
+        let mut res = error_reply(
+            Error::ServiceUnavailable(*secs).to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        );
+        res.headers_mut().insert(RETRY_AFTER, HeaderValue::from(*secs));
+        Ok(res)
+    } else if let Some(error @ crate::Error::OffensiveContent(_)) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(
             error.to_string(),
             StatusCode::UNPROCESSABLE_ENTITY,
         ))
-    } else if let Some(error) = r.find::<CorsForbidden>() {
-        event!(Level::ERROR, "CORS forbidden error: {}", error);
-        Ok(warp::reply::with_status(
+    } else if let Some(error @ crate::Error::UnsupportedMediaType(_, supported)) = r.find() {
+        event!(Level::WARN, "{}", error);
+        let mut res = error_reply(error.to_string(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        res.headers_mut().insert(
+            ACCEPT,
+            HeaderValue::from_str(&supported.join(", ")).unwrap(),
+        );
+        Ok(res)
+    } else if let Some(error @ crate::Error::NotAcceptable(_)) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(error.to_string(), StatusCode::NOT_ACCEPTABLE))
+    } else if let Some(error @ crate::Error::UnsupportedEncoding(_, supported)) = r.find() {
+        event!(Level::WARN, "{}", error);
+        let mut res = error_reply(error.to_string(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        res.headers_mut().insert(
+            ACCEPT_ENCODING,
+            HeaderValue::from_str(&supported.join(", ")).unwrap(),
+        );
+        Ok(res)
+    } else if let Some(error @ crate::Error::PayloadTooLarge(_)) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(error.to_string(), StatusCode::PAYLOAD_TOO_LARGE))
+    } else if let Some(error @ crate::Error::InvalidIdempotencyKey) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(error.to_string(), StatusCode::BAD_REQUEST))
+    } else if let Some(error @ crate::Error::IdempotencyKeyInUse) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(error.to_string(), StatusCode::CONFLICT))
+    } else if let Some(error @ crate::Error::IdempotencyKeyReused) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(error.to_string(), StatusCode::UNPROCESSABLE_ENTITY))
+    } else if let Some(error @ crate::Error::CorsForbidden { .. }) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(error.to_string(), StatusCode::FORBIDDEN))
+    } else if let Some(error @ crate::Error::WebSocketRequired) = r.find() {
+        event!(Level::WARN, "{}", error);
+        let mut res = error_reply(error.to_string(), StatusCode::UPGRADE_REQUIRED);
+        res.headers_mut().insert(UPGRADE, HeaderValue::from_static("websocket"));
+        Ok(res)
+    } else if let Some(error @ crate::Error::Unauthorized) = r.find() {
+        event!(Level::WARN, "{}", error);
+        let mut res = error_reply(error.to_string(), StatusCode::UNAUTHORIZED);
+        res.headers_mut().insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        Ok(res)
+    } else if let Some(error @ crate::Error::QuestionNotFound(_)) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(error.to_string(), StatusCode::NOT_FOUND))
+    } else if let Some(error @ crate::Error::AnswerNotFound(_)) = r.find() {
+        event!(Level::WARN, "{}", error);
+        Ok(error_reply(error.to_string(), StatusCode::NOT_FOUND))
+    } else if let Some(error) = r.find::<Error>() {
+        event!(Level::ERROR, "{}", error);
+        Ok(error_reply(
             error.to_string(),
-            StatusCode::FORBIDDEN,
+            StatusCode::UNPROCESSABLE_ENTITY,
         ))
     } else if let Some(error) = r.find::<BodyDeserializeError>() {
         event!(Level::ERROR, "Cannot deserialize request body: {}", error);
-        Ok(warp::reply::with_status(
+        Ok(error_reply(
             error.to_string(),
             StatusCode::UNPROCESSABLE_ENTITY,
         ))
     } else {
         event!(Level::WARN, "Requested route was not found");
-        Ok(warp::reply::with_status(
+        Ok(error_reply(
             "Route not found".to_string(),
             StatusCode::NOT_FOUND,
         ))
     }
 }
+
+/// Reply with an error message, mentioning the request id so clients
+/// can refer to it when reporting problems
+fn error_reply(message: String, status: StatusCode) -> warp::reply::Response {
+    let message = match current_request_id() {
+        Some(id) => format!("{} (request id: {})", message, id),
+        None => message,
+    };
+    warp::reply::with_status(message, status).into_response()
+}
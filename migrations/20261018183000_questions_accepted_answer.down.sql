@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE questions DROP COLUMN IF EXISTS accepted_answer;
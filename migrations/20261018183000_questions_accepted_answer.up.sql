@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS accepted_answer integer REFERENCES answers ON DELETE SET NULL;
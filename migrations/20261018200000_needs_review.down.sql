@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE answers DROP COLUMN IF EXISTS needs_review;
+ALTER TABLE questions DROP COLUMN IF EXISTS needs_review;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS moderation_jobs;
+ALTER TABLE answers DROP COLUMN IF EXISTS status;
+ALTER TABLE questions DROP COLUMN IF EXISTS status;
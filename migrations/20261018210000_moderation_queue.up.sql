@@ -0,0 +1,19 @@
+-- Add up migration script here
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'approved'
+    CHECK (status IN ('pending_moderation', 'approved', 'rejected'));
+ALTER TABLE answers ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'approved'
+    CHECK (status IN ('pending_moderation', 'approved', 'rejected'));
+
+CREATE TABLE IF NOT EXISTS moderation_jobs (
+    id serial PRIMARY KEY,
+    question_id integer REFERENCES questions ON DELETE CASCADE,
+    answer_id integer REFERENCES answers ON DELETE CASCADE,
+    attempts integer NOT NULL DEFAULT 0,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    dead_at TIMESTAMPTZ,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK ((question_id IS NULL) <> (answer_id IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS moderation_jobs_runnable ON moderation_jobs (run_at) WHERE dead_at IS NULL;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE moderation_jobs DROP COLUMN IF EXISTS route;
+DROP TABLE IF EXISTS profanity_audit;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS profanity_audit (
+    id serial PRIMARY KEY,
+    route TEXT NOT NULL,
+    field TEXT NOT NULL,
+    policy TEXT NOT NULL,
+    question_id integer REFERENCES questions ON DELETE SET NULL,
+    answer_id integer REFERENCES answers ON DELETE SET NULL,
+    request_id TEXT,
+    original TEXT NOT NULL,
+    censored TEXT NOT NULL,
+    bad_words_total integer NOT NULL,
+    bad_words JSONB NOT NULL,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE moderation_jobs ADD COLUMN IF NOT EXISTS route TEXT;
+UPDATE moderation_jobs SET route = CASE WHEN answer_id IS NULL THEN 'add_question' ELSE 'add_answer' END
+WHERE route IS NULL;
+ALTER TABLE moderation_jobs ALTER COLUMN route SET NOT NULL;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS author text;
+ALTER TABLE answers ADD COLUMN IF NOT EXISTS author text;
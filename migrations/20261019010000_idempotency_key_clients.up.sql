@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE idempotency_keys ADD COLUMN IF NOT EXISTS client TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE idempotency_keys DROP CONSTRAINT IF EXISTS idempotency_keys_pkey;
+ALTER TABLE idempotency_keys ADD PRIMARY KEY (route, client, key);
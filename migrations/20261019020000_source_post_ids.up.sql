@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE questions ADD COLUMN IF NOT EXISTS source_post_id text UNIQUE;
+ALTER TABLE answers ADD COLUMN IF NOT EXISTS source_post_id text UNIQUE;
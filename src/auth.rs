@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use handle_errors::Error;
+use sha2::{Digest, Sha256};
+use warp::{Filter, Rejection};
+
+/// Guards the `/admin` routes, which can read hidden content and
+/// overwrite anything, with a bearer token. Without a configured token
+/// every admin request is refused.
+#[derive(Clone)]
+pub struct AdminAuth {
+    /// SHA-256 of the token, so comparisons take the same time
+    /// however much of a guess is right
+    digest: Option<Arc<[u8]>>,
+}
+
+impl std::fmt::Debug for AdminAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdminAuth")
+            .field("enabled", &self.digest.is_some())
+            .finish()
+    }
+}
+
+impl AdminAuth {
+    pub fn new(token: Option<&str>) -> Self {
+        AdminAuth {
+            digest: token.map(|token| Sha256::digest(token.as_bytes()).to_vec().into()),
+        }
+    }
+
+    /// Let a request through only if it carries `Authorization: Bearer <token>`
+    pub fn require(&self) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let digest = self.digest.clone();
+        warp::header::optional::<String>("authorization")
+            .and_then(move |authorization: Option<String>| {
+                let digest = digest.clone();
+                async move {
+                    let presented = authorization
+                        .as_deref()
+                        .and_then(|value| value.strip_prefix("Bearer "))
+                        .map(|token| Sha256::digest(token.trim().as_bytes()));
+
+                    match (digest, presented) {
+                        (Some(digest), Some(presented)) if *digest == presented[..] => Ok(()),
+                        _ => Err(warp::reject::custom(Error::Unauthorized)),
+                    }
+                }
+            })
+            .untuple_one()
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt, TryStreamExt};
+use handle_errors::Error;
+use warp::hyper::body::Bytes;
+use warp::{Buf, Filter, Rejection};
+
+use crate::compression;
+use crate::types::body_limit::BodyLimitRule;
+
+/// Limit of routes without a configured one, in bytes
+pub const DEFAULT_LIMIT: u64 = 1 << 20;
+
+/// A decoded request body which fails once it exceeds the route's limit
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>;
+
+/// Largest request body accepted by each route. Limits apply to the
+/// decompressed body, so a small gzip bomb is cut off like a large body.
+#[derive(Clone, Debug)]
+pub struct BodyLimits {
+    limits: Arc<HashMap<String, u64>>,
+}
+
+impl BodyLimits {
+    pub fn new(rules: Vec<BodyLimitRule>) -> Self {
+        BodyLimits {
+            limits: Arc::new(rules.into_iter().map(|r| (r.route, r.bytes)).collect()),
+        }
+    }
+
+    fn limit(&self, route: &str) -> u64 {
+        self.limits.get(route).copied().unwrap_or(DEFAULT_LIMIT)
+    }
+
+    /// Stream the request body of `route`, decoded per its `Content-Encoding`.
+    /// Bodies announced larger than the limit are rejected with
+    /// `Error::PayloadTooLarge` before reading them, others fail with it
+    /// as soon as the limit is exceeded.
+    pub fn stream(
+        &self,
+        route: &'static str,
+    ) -> impl Filter<Extract = (BodyStream,), Error = Rejection> + Clone {
+        let limit = self.limit(route);
+        warp::header::optional::<String>("content-encoding")
+            .and(warp::header::optional::<u64>("content-length"))
+            .and(warp::body::stream())
+            .and(warp::any().map(move || limit))
+            .and_then(open)
+    }
+
+    /// Read the whole request body of `route`, as by `stream`
+    pub fn bytes(
+        &self,
+        route: &'static str,
+    ) -> impl Filter<Extract = (Bytes,), Error = Rejection> + Clone {
+        self.stream(route).and_then(|body: BodyStream| async move {
+            body.try_fold(Vec::new(), |mut all, chunk| async move {
+                all.extend_from_slice(&chunk);
+                Ok(all)
+            })
+            .await
+            .map(Bytes::from)
+            .map_err(warp::reject::custom)
+        })
+    }
+}
+
+async fn open(
+    encoding: Option<String>,
+    length: Option<u64>,
+    body: impl Stream<Item = Result<impl Buf, warp::Error>> + Send + 'static,
+    limit: u64,
+) -> Result<BodyStream, Rejection> {
+    if encoding.is_none() && length.is_some_and(|length| length > limit) {
+        return Err(warp::reject::custom(Error::PayloadTooLarge(limit)));
+    }
+
+    let body = body
+        .map_ok(|mut chunk| chunk.copy_to_bytes(chunk.remaining()))
+        .map_err(io::Error::other);
+    let body = compression::decode(encoding.as_deref(), body)?;
+    Ok(limited(body, limit))
+}
+
+fn limited(body: compression::DecodedBody, limit: u64) -> BodyStream {
+    let mut total = 0;
+    Box::pin(body.map(move |chunk| {
+        let chunk = chunk.map_err(|e| Error::InvalidBody(e.to_string()))?;
+        total += chunk.len() as u64;
+        if total > limit {
+            return Err(Error::PayloadTooLarge(limit));
+        }
+        Ok(chunk)
+    }))
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::metrics;
+
+/// Stops calls to the profanity API for a while once it keeps failing,
+/// instead of letting every request wait for its timeouts and retries.
+/// After `threshold` consecutive failures the circuit opens for `cool_down`;
+/// then a single trial call decides whether it closes again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cool_down: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// A trial call is running. Another one is let through should it
+    /// not have reported back after `cool_down`, e.g. because it was cancelled.
+    HalfOpen { until: Instant },
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cool_down: Duration) -> Self {
+        metrics::set_profanity_circuit_state(0);
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            cool_down,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a call may go to the backend. Every allowed call has to
+    /// be followed by `record`.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock();
+        let now = Instant::now();
+
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } | State::HalfOpen { until } if now < until => false,
+            State::Open { .. } | State::HalfOpen { .. } => {
+                self.transition(&mut state, State::HalfOpen { until: now + self.cool_down });
+                true
+            }
+        }
+    }
+
+    pub fn record(&self, success: bool) {
+        let mut state = self.state.lock();
+
+        let next = match (*state, success) {
+            (_, true) => State::Closed { failures: 0 },
+            (State::Closed { failures }, false) if failures + 1 < self.threshold => {
+                State::Closed { failures: failures + 1 }
+            }
+            (_, false) => {
+                tracing::warn!("Profanity API keeps failing, opening circuit for {:?}", self.cool_down);
+                State::Open { until: Instant::now() + self.cool_down }
+            }
+        };
+        self.transition(&mut state, next);
+    }
+
+    /// Whether calls are held back, or only a trial call is let through
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.state.lock(), State::Closed { .. })
+    }
+
+    /// Seconds until a call will be attempted again, rounded up
+    pub fn retry_after(&self) -> u64 {
+        match *self.state.lock() {
+            State::Open { until } | State::HalfOpen { until } => {
+                let left = until.saturating_duration_since(Instant::now());
+                left.as_secs() + u64::from(left.subsec_nanos() > 0)
+            }
+            State::Closed { .. } => 0,
+        }
+        .max(1)
+    }
+
+    fn transition(&self, state: &mut State, next: State) {
+        *state = next;
+        metrics::set_profanity_circuit_state(match next {
+            State::Closed { .. } => 0,
+            State::Open { .. } => 1,
+            State::HalfOpen { .. } => 2,
+        });
+    }
+}
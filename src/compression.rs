@@ -0,0 +1,153 @@
+use std::io;
+use std::pin::Pin;
+
+use async_compression::tokio::bufread::{BrotliEncoder, GzipDecoder, GzipEncoder};
+use async_compression::Level;
+use futures::{Stream, TryStreamExt};
+use handle_errors::Error;
+use tokio_util::io::{ReaderStream, StreamReader};
+use warp::http::{header, HeaderValue, StatusCode};
+use warp::hyper::body::{Bytes, HttpBody};
+use warp::hyper::Body;
+use warp::{Filter, Rejection, Reply};
+
+/// Content encodings request bodies are accepted in
+pub const REQUEST_ENCODINGS: &[&str] = &["gzip", "identity"];
+
+/// Smallest response body worth compressing, in bytes
+const MIN_SIZE: u64 = 1024;
+
+/// Media types of responses which are compressed. Others, like the
+/// images of the Swagger UI, are compressed already.
+const COMPRESSIBLE: &[&str] = &[
+    "text/",
+    "application/json",
+    "application/x-ndjson",
+    "application/javascript",
+    "application/msgpack",
+];
+
+/// An encoding of response bodies, from most to least preferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Identity,
+}
+
+impl Encoding {
+    fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Identity => "identity",
+        }
+    }
+}
+
+/// Pick the encoding to compress the response in by the client's
+/// `Accept-Encoding` header. Without one, responses aren't compressed.
+pub fn accept_encoding() -> impl Filter<Extract = (Encoding,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept-encoding")
+        .map(|accept: Option<String>| accept.map_or(Encoding::Identity, |accept| choose(&accept)))
+}
+
+/// The encoding with the highest quality in `accept`, where a coding not
+/// listed gets the quality of `*`. Ties go to the more preferred encoding.
+fn choose(accept: &str) -> Encoding {
+    let codings: Vec<(String, f32)> = accept
+        .split(',')
+        .filter_map(|coding| {
+            let mut parts = coding.split(';');
+            let name = parts.next()?.trim().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            Some((name, quality))
+        })
+        .collect();
+    let quality = |names: &[&str]| {
+        codings
+            .iter()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .or_else(|| codings.iter().find(|(name, _)| name == "*"))
+            .map_or(0.0, |(_, quality)| *quality)
+    };
+
+    let brotli = quality(&["br"]);
+    let gzip = quality(&["gzip", "x-gzip"]);
+    match (brotli, gzip) {
+        (b, g) if b > 0.0 && b >= g => Encoding::Brotli,
+        (_, g) if g > 0.0 => Encoding::Gzip,
+        _ => Encoding::Identity,
+    }
+}
+
+/// Compress the body of a reply in `encoding`, unless it is too small,
+/// already encoded or of a media type that doesn't compress well
+pub fn compress(encoding: Encoding, reply: impl Reply) -> warp::reply::Response {
+    let res = reply.into_response();
+    if encoding == Encoding::Identity || !compressible(&res) {
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+    let body = StreamReader::new(TryStreamExt::map_err(body, io::Error::other));
+    let body = match encoding {
+        // Fast enough to compress every response on the fly
+        Encoding::Brotli => Body::wrap_stream(ReaderStream::new(BrotliEncoder::with_quality(
+            body,
+            Level::Precise(4),
+        ))),
+        Encoding::Gzip => Body::wrap_stream(ReaderStream::new(GzipEncoder::new(body))),
+        Encoding::Identity => unreachable!(),
+    };
+
+    parts
+        .headers
+        .insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    warp::reply::Response::from_parts(parts, body)
+}
+
+fn compressible(res: &warp::reply::Response) -> bool {
+    if matches!(res.status(), StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED)
+        || res.headers().contains_key(header::CONTENT_ENCODING)
+    {
+        return false;
+    }
+    if HttpBody::size_hint(res.body()).exact().is_some_and(|size| size < MIN_SIZE) {
+        return false;
+    }
+
+    res.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| COMPRESSIBLE.iter().any(|t| content_type.starts_with(t)))
+}
+
+/// A request body decoded according to its `Content-Encoding`
+pub type DecodedBody = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// Decode a request body sent in `encoding`
+pub fn decode(
+    encoding: Option<&str>,
+    body: impl Stream<Item = io::Result<Bytes>> + Send + 'static,
+) -> Result<DecodedBody, Error> {
+    let encoding = encoding.map(|e| e.trim().to_ascii_lowercase());
+
+    match encoding.as_deref() {
+        None | Some("identity") => Ok(Box::pin(body)),
+        Some("gzip") | Some("x-gzip") => Ok(Box::pin(ReaderStream::new(GzipDecoder::new(
+            StreamReader::new(body),
+        )))),
+        Some(other) => Err(Error::UnsupportedEncoding(
+            other.to_string(),
+            REQUEST_ENCODINGS.to_vec(),
+        )),
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use handle_errors::Error;
+use warp::http::header::{self, HeaderName, HeaderValue};
+use warp::http::Method;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::cli::ServeArgs;
+use crate::rate_limit::ClientAddr;
+use crate::types::cors::CorsOrigin;
+use crate::types::network::IpNetwork;
+
+/// Which cross-origin requests browsers may make to the API, and what
+/// they may read from the responses
+#[derive(Debug)]
+pub struct Cors {
+    origins: Vec<CorsOrigin>,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    credentials: bool,
+    allow_methods: HeaderValue,
+    allow_headers: HeaderValue,
+    expose_headers: Option<HeaderValue>,
+    max_age: HeaderValue,
+    /// Whether the API is served over TLS itself
+    tls: bool,
+    /// Proxies trusted to tell the scheme in `X-Forwarded-Proto`
+    trusted_proxies: Vec<IpNetwork>,
+}
+
+impl Cors {
+    pub fn new(args: &ServeArgs) -> Result<Arc<Self>, String> {
+        if args.cors_allow_credentials && args.cors_origins.contains(&CorsOrigin::Any) {
+            return Err("credentials can't be allowed for any origin `*`".to_string());
+        }
+
+        let join = |names: Vec<&str>| HeaderValue::from_str(&names.join(", ")).unwrap();
+        Ok(Arc::new(Cors {
+            origins: args.cors_origins.clone(),
+            methods: args.cors_methods.clone(),
+            headers: args.cors_headers.clone(),
+            credentials: args.cors_allow_credentials,
+            allow_methods: join(args.cors_methods.iter().map(Method::as_str).collect()),
+            allow_headers: join(args.cors_headers.iter().map(HeaderName::as_str).collect()),
+            expose_headers: (!args.cors_expose_headers.is_empty()).then(|| {
+                join(args.cors_expose_headers.iter().map(HeaderName::as_str).collect())
+            }),
+            max_age: HeaderValue::from(args.cors_max_age),
+            tls: args.tls_cert.is_some(),
+            trusted_proxies: args.trusted_proxies.clone(),
+        }))
+    }
+
+    /// The scheme a request was made with. Behind a trusted proxy it is
+    /// the one the proxy says it was made with.
+    fn scheme<'a>(&self, client: Option<ClientAddr>, forwarded_proto: Option<&'a str>) -> &'a str {
+        if self.tls {
+            return "https";
+        }
+        let proxied = client.is_some_and(|ClientAddr(addr)| {
+            self.trusted_proxies.iter().any(|proxy| proxy.contains(addr.ip()))
+        });
+        match forwarded_proto.filter(|_| proxied) {
+            // The first is the one of the proxy nearest to the client
+            Some(proto) => proto.split(',').next().unwrap_or_default().trim(),
+            None => "http",
+        }
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.origins.iter().any(|allowed| allowed.matches(origin))
+    }
+
+    fn check_origin(&self, origin: &str) -> Result<(), Rejection> {
+        if self.allows(origin) {
+            Ok(())
+        } else {
+            Err(forbidden(origin, "origin not allowed".to_string()))
+        }
+    }
+
+    fn preflight(
+        &self,
+        origin: &str,
+        method: &str,
+        headers: Option<&str>,
+    ) -> Result<Response, Rejection> {
+        self.check_origin(origin)?;
+        if !self.methods.iter().any(|allowed| allowed.as_str() == method) {
+            return Err(forbidden(origin, format!("method `{}` not allowed", method)));
+        }
+        let requested = headers.into_iter().flat_map(|headers| headers.split(','));
+        for name in requested.map(str::trim).filter(|name| !name.is_empty()) {
+            if !self.headers.iter().any(|allowed| allowed.as_str().eq_ignore_ascii_case(name)) {
+                return Err(forbidden(origin, format!("header `{}` not allowed", name)));
+            }
+        }
+
+        let mut res = Response::default();
+        let res_headers = res.headers_mut();
+        res_headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, self.allow_methods.clone());
+        res_headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, self.allow_headers.clone());
+        res_headers.insert(header::ACCESS_CONTROL_MAX_AGE, self.max_age.clone());
+        Ok(res)
+    }
+
+    /// Let the page of an allowed `origin` read `reply`, errors included
+    pub fn apply(&self, origin: Option<String>, reply: impl Reply) -> Response {
+        let mut res = reply.into_response();
+        let headers = res.headers_mut();
+        // The allowed origin is echoed, so caches need to tell them apart
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+
+        let origin = origin
+            .filter(|origin| self.allows(origin))
+            .and_then(|origin| HeaderValue::from_str(&origin).ok());
+        let origin = match origin {
+            Some(origin) => origin,
+            None => return res,
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Some(expose) = &self.expose_headers {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, expose.clone());
+        }
+        res
+    }
+}
+
+/// The `Origin` of the request, if any
+pub fn origin() -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Copy {
+    warp::header::optional::<String>("origin")
+}
+
+/// Answer preflight requests of allowed origins with the methods and
+/// headers they may use, rejecting the others with `CorsForbidden`
+pub fn preflight(
+    cors: Arc<Cors>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    warp::options()
+        .and(warp::header::<String>("origin"))
+        .and(warp::header::<String>("access-control-request-method"))
+        .and(warp::header::optional::<String>("access-control-request-headers"))
+        .and_then(move |origin: String, method: String, headers: Option<String>| {
+            let result = cors.preflight(&origin, &method, headers.as_deref());
+            async move { result }
+        })
+}
+
+/// Reject requests from origins which aren't allowed with `CorsForbidden`.
+/// Requests without an `Origin`, or from the API's own, always pass.
+pub fn check(cors: Arc<Cors>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    origin()
+        .and(warp::header::optional::<String>("host"))
+        .and(warp::ext::optional::<ClientAddr>())
+        .and(warp::header::optional::<String>("x-forwarded-proto"))
+        .and_then(
+            move |origin: Option<String>,
+                  host: Option<String>,
+                  client: Option<ClientAddr>,
+                  forwarded_proto: Option<String>| {
+                let scheme = cors.scheme(client, forwarded_proto.as_deref());
+                let result = match origin {
+                    Some(origin) if !same_origin(&origin, scheme, host.as_deref()) => {
+                        cors.check_origin(&origin)
+                    }
+                    _ => Ok(()),
+                };
+                async move { result }
+            },
+        )
+        .untuple_one()
+}
+
+/// Whether `origin` is the API's own, e.g. the Swagger UI posting to it.
+/// A page served over plain HTTP is another origin than the API over HTTPS.
+fn same_origin(origin: &str, scheme: &str, host: Option<&str>) -> bool {
+    match (origin.split_once("://"), host) {
+        (Some((origin_scheme, origin_host)), Some(host)) => {
+            origin_scheme.eq_ignore_ascii_case(scheme) && origin_host.eq_ignore_ascii_case(host)
+        }
+        _ => false,
+    }
+}
+
+fn forbidden(origin: &str, reason: String) -> Rejection {
+    warp::reject::custom(Error::CorsForbidden { origin: origin.to_string(), reason })
+}
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use crate::store::Store;
+use crate::types::event::Event;
+use crate::types::question::QuestionId;
+
+/// Postgres channel events are shared with other instances on
+pub const CHANNEL: &str = "qa_events";
+
+/// Events kept for subscribers which read slower than they are published.
+/// Subscribers further behind miss the oldest.
+const CAPACITY: usize = 1024;
+
+/// In-process bus of question and answer events, which the `Store` write
+/// paths publish to. When shared, events also reach the buses of other
+/// instances through Postgres `NOTIFY`, and theirs reach ours through
+/// `listen`. Bulk imports and seeding don't publish events.
+#[derive(Clone, Debug)]
+pub struct Events {
+    sender: broadcast::Sender<Event>,
+    /// Tells our own notifications apart from other instances', when shared
+    instance: Option<Arc<str>>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            sender: broadcast::channel(CAPACITY).0,
+            instance: None,
+        }
+    }
+}
+
+impl Events {
+    /// Share events with other instances from now on
+    pub fn share(&mut self) {
+        self.instance = Some(uuid::Uuid::new_v4().to_string().into());
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Send `event` to the subscribers of this instance, returning the
+    /// payload to notify other instances with, if shared
+    pub(crate) fn send(&self, event: Event) -> Option<String> {
+        let notification = self.instance.as_ref().map(|instance| {
+            // Only consists of strings and numbers, which always serialize
+            serde_json::to_string(&Notification {
+                instance: instance.to_string(),
+                change: Change::from(&event),
+            })
+            .unwrap()
+        });
+        // Nobody listening is fine
+        let _ = self.sender.send(event);
+        notification
+    }
+}
+
+/// Payload of a notification. Notifications are limited to 8000 bytes,
+/// so only the changed row is named, and other instances read it again.
+#[derive(Debug, Serialize, Deserialize)]
+struct Notification {
+    instance: String,
+    #[serde(flatten)]
+    change: Change,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Change {
+    QuestionCreated { id: i32 },
+    QuestionUpdated { id: i32 },
+    /// Deleted rows can't be read again, so this one is complete
+    QuestionDeleted { id: i32, tags: Option<Vec<String>> },
+    AnswerCreated { id: i32 },
+}
+
+impl From<&Event> for Change {
+    fn from(event: &Event) -> Self {
+        match event {
+            Event::QuestionCreated { question } => Change::QuestionCreated { id: question.id.0 },
+            Event::QuestionUpdated { question } => Change::QuestionUpdated { id: question.id.0 },
+            Event::QuestionDeleted { question_id, tags } => Change::QuestionDeleted {
+                id: question_id.0,
+                tags: tags.clone(),
+            },
+            Event::AnswerCreated { answer, .. } => Change::AnswerCreated { id: answer.id.0 },
+        }
+    }
+}
+
+/// Publish the events of other instances on our bus until `stop` is
+/// cancelled. Notifications sent while the connection is down are lost.
+pub fn listen(store: Store, stop: CancellationToken) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut listener = loop {
+            let listener = async {
+                let mut listener = PgListener::connect_with(&store.connection).await?;
+                listener.listen(CHANNEL).await?;
+                Ok::<_, sqlx::Error>(listener)
+            };
+            match listener.await {
+                Ok(listener) => break listener,
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "Cannot listen for events: {:?}", e);
+                    tokio::select! {
+                        _ = stop.cancelled() => return,
+                        _ = tokio::time::sleep(Duration::from_secs(1)) => continue,
+                    }
+                }
+            }
+        };
+
+        loop {
+            let notification = tokio::select! {
+                _ = stop.cancelled() => return,
+                notification = listener.recv() => notification,
+            };
+            let notification = match notification {
+                Ok(notification) => notification,
+                Err(sqlx::Error::PoolClosed) => return,
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "Cannot receive events: {:?}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+
+            let notification: Notification = match serde_json::from_str(notification.payload()) {
+                Ok(notification) => notification,
+                Err(e) => {
+                    tracing::event!(tracing::Level::WARN, "Ignoring unknown event: {}", e);
+                    continue;
+                }
+            };
+            if store.events.instance.as_deref() == Some(notification.instance.as_str()) {
+                continue;
+            }
+            match read(&store, notification.change).await {
+                Ok(Some(event)) => {
+                    // Not shared again, the other instances got it already
+                    let _ = store.events.sender.send(event);
+                }
+                // Deleted or hidden again since
+                Ok(None) => {}
+                Err(e) => tracing::event!(tracing::Level::ERROR, "Cannot read event: {}", e),
+            }
+        }
+    })
+}
+
+/// The event of another instance's change, as the row reads now
+async fn read(store: &Store, change: Change) -> Result<Option<Event>, handle_errors::Error> {
+    Ok(match change {
+        Change::QuestionCreated { id } => store
+            .get_question(id)
+            .await?
+            .map(|question| Event::QuestionCreated { question }),
+        Change::QuestionUpdated { id } => store
+            .get_question(id)
+            .await?
+            .map(|question| Event::QuestionUpdated { question }),
+        Change::QuestionDeleted { id, tags } => Some(Event::QuestionDeleted {
+            question_id: QuestionId(id),
+            tags,
+        }),
+        Change::AnswerCreated { id } => match store.get_answer(id).await? {
+            Some(answer) => store
+                .get_question(answer.question_id.0)
+                .await?
+                .map(|question| Event::AnswerCreated { answer, tags: question.tags }),
+            None => None,
+        },
+    })
+}
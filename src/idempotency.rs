@@ -0,0 +1,169 @@
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use handle_errors::Error;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio_util::sync::CancellationToken;
+use warp::{Filter, Rejection};
+
+use crate::negotiate::Format;
+use crate::rate_limit;
+use crate::store::Store;
+use crate::types::idempotency::{Claim, Recorded};
+use crate::types::network::IpNetwork;
+
+/// Longest accepted `Idempotency-Key`
+const MAX_KEY_LEN: usize = 255;
+
+/// How often expired keys are deleted
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Replays the response to a request retried with the same
+/// `Idempotency-Key` header instead of handling it again. Keys are scoped
+/// to a route and the client's address, and kept in the database for
+/// `ttl`, so a retry may reach any instance.
+#[derive(Clone, Debug)]
+pub struct Idempotency {
+    store: Store,
+    ttl: Duration,
+    trusted_proxies: Arc<[IpNetwork]>,
+}
+
+/// The `Idempotency-Key` of a request to a route
+#[derive(Debug)]
+pub struct IdempotencyKey {
+    idempotency: Idempotency,
+    route: &'static str,
+    /// The client's address, empty when it isn't known
+    client: String,
+    key: String,
+}
+
+impl Idempotency {
+    pub fn new(store: Store, ttl: Duration, trusted_proxies: Vec<IpNetwork>) -> Self {
+        Idempotency { store, ttl, trusted_proxies: trusted_proxies.into() }
+    }
+
+    /// Extract the `Idempotency-Key` of a request to `route`, if it has one
+    pub fn key(
+        &self,
+        route: &'static str,
+    ) -> impl Filter<Extract = (Option<IdempotencyKey>,), Error = Rejection> + Clone {
+        let idempotency = self.clone();
+        warp::header::optional::<String>("idempotency-key")
+            .and(rate_limit::client(self.trusted_proxies.clone()))
+            .and_then(move |key: Option<String>, client: Option<IpAddr>| {
+                let idempotency = idempotency.clone();
+                async move {
+                    match key {
+                        None => Ok(None),
+                        Some(key) if key.is_empty() || key.len() > MAX_KEY_LEN => {
+                            Err(warp::reject::custom(Error::InvalidIdempotencyKey))
+                        }
+                        Some(key) => Ok(Some(IdempotencyKey {
+                            idempotency,
+                            route,
+                            client: client.map(|addr| addr.to_string()).unwrap_or_default(),
+                            key,
+                        })),
+                    }
+                }
+            })
+    }
+}
+
+/// Hash of a request body and the format its response is sent in, to
+/// tell it from other requests sent with the same key
+pub fn fingerprint<T: Serialize>(format: Format, request: &T) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(format.media_type());
+    // Media types never contain a NUL byte, so it ends the media type unambiguously
+    hasher.update([0]);
+    // Request types only consist of strings, numbers and lists, which always serialize
+    hasher.update(serde_json::to_vec(request).unwrap());
+    hasher.finalize().to_vec()
+}
+
+/// Delete the keys which expired, until `stop` is cancelled. Claiming an
+/// expired key takes it over, so this only keeps the table small.
+pub fn sweep(store: Store, ttl: Duration, stop: CancellationToken) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = stop.cancelled() => return,
+                _ = ticks.tick() => {}
+            }
+            // Failures are logged by the store, and retried on the next tick
+            let _ = store.sweep_idempotency_keys(ttl).await;
+        }
+    })
+}
+
+/// Run `handle` unless a request with the same key and `fingerprint` was
+/// handled before, in which case its response is replayed. Only successful
+/// responses are kept: when `handle` fails, or the client goes away before
+/// it finishes, the key is released so the request can be retried.
+pub async fn once(
+    key: Option<IdempotencyKey>,
+    fingerprint: Vec<u8>,
+    handle: impl Future<Output = Result<Recorded, Rejection>>,
+) -> Result<Recorded, Rejection> {
+    let key = match key {
+        Some(key) => key,
+        None => return handle.await,
+    };
+    let store = &key.idempotency.store;
+
+    match store
+        .claim_idempotency_key(key.route, &key.client, &key.key, &fingerprint, key.idempotency.ttl)
+        .await?
+    {
+        Claim::Claimed => {}
+        Claim::Replay(res) => return Ok(res),
+        Claim::InProgress => return Err(warp::reject::custom(Error::IdempotencyKeyInUse)),
+        Claim::Mismatch => return Err(warp::reject::custom(Error::IdempotencyKeyReused)),
+    }
+
+    let mut release = Release(Some(key));
+    let res = handle.await;
+    // Not dropped while awaiting `handle`, so always set
+    let key = release.0.take().unwrap();
+    let store = &key.idempotency.store;
+
+    match res {
+        Ok(res) => {
+            // The resource was created either way, so the response is still
+            // sent. The key is released, as a claim without a response would
+            // refuse every retry until it expires.
+            if store.record_idempotent_response(key.route, &key.client, &key.key, &res).await.is_err() {
+                let _ = store.release_idempotency_key(key.route, &key.client, &key.key).await;
+            }
+            Ok(res)
+        }
+        Err(e) => {
+            let _ = store.release_idempotency_key(key.route, &key.client, &key.key).await;
+            Err(e)
+        }
+    }
+}
+
+/// Releases a claimed key when the request handling it is dropped
+struct Release(Option<IdempotencyKey>);
+
+impl Drop for Release {
+    fn drop(&mut self) {
+        if let Some(key) = self.0.take() {
+            tokio::spawn(async move {
+                let _ = key
+                    .idempotency
+                    .store
+                    .release_idempotency_key(key.route, &key.client, &key.key)
+                    .await;
+            });
+        }
+    }
+}
@@ -0,0 +1,28 @@
+#![warn(clippy::all)]
+
+mod circuit_breaker;
+pub mod auth;
+pub mod body;
+pub mod cli;
+pub mod compression;
+pub mod cors;
+pub mod events;
+pub mod idempotency;
+pub mod logging;
+pub mod metrics;
+pub mod migrate;
+pub mod moderation;
+pub mod negotiate;
+pub mod openapi;
+pub mod profanity;
+pub mod rate_limit;
+pub mod request_id;
+pub mod routes;
+pub mod seed;
+pub mod server;
+pub mod shutdown;
+pub mod stackexchange;
+pub mod store;
+pub mod telemetry;
+pub mod tls;
+pub mod types;
@@ -0,0 +1,102 @@
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    fmt::{format::FmtSpan, MakeWriter},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter, Layer, Registry,
+};
+
+use crate::cli::{LogArgs, LogFormat, LogRotation};
+use crate::telemetry;
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Flushes buffered file logs and exports pending spans when dropped
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Cannot flush pending spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Install the global tracing subscriber.
+/// The returned guard has to be held until the program exits.
+pub fn init(args: &LogArgs) -> LogGuard {
+    let log_filter = std::env::var("RUST_LOG")
+        .unwrap_or_else(|_| "minimal_warp=info,warp=error".to_owned());
+
+    let mut layers: Vec<BoxedLayer> = Vec::new();
+    let mut file_guard = None;
+    let mut tracer_provider = None;
+
+    if !args.no_log_stdout {
+        layers.push(fmt_layer(args.log_format, std::io::stdout));
+    }
+
+    if let Some(path) = &args.log_file {
+        let mut condition = match args.log_rotation {
+            LogRotation::Daily => RollingConditionBasic::new().daily(),
+            LogRotation::Hourly => RollingConditionBasic::new().hourly(),
+            LogRotation::Never => RollingConditionBasic::new(),
+        };
+        if let Some(mb) = args.log_max_size {
+            condition = condition.max_size(mb * 1024 * 1024);
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).expect("Cannot create log directory");
+        }
+        let appender = BasicRollingFileAppender::new(path, condition, args.log_max_files)
+            .expect("Cannot open log file");
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+        // Files are meant for machines, so they are always JSON
+        layers.push(fmt_layer(LogFormat::Json, writer));
+        file_guard = Some(guard);
+    }
+
+    if let Some(endpoint) = &args.otlp_endpoint {
+        let provider = telemetry::tracer_provider(endpoint, args.otlp_protocol);
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        layers.push(tracing_opentelemetry::layer().with_tracer(tracer).boxed());
+        tracer_provider = Some(provider);
+    }
+
+    tracing_subscriber::registry()
+        // Use above filter to determine which traces to record
+        .with(layers.with_filter(EnvFilter::new(log_filter)))
+        .init();
+
+    LogGuard {
+        _file: file_guard,
+        tracer_provider,
+    }
+}
+
+fn fmt_layer<W>(format: LogFormat, writer: W) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        // Record an event when each span closes.
+        // This can be used to time routes' durations.
+        .with_span_events(FmtSpan::CLOSE);
+
+    match format {
+        LogFormat::Full => layer.boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Json => layer.json().with_current_span(true).with_span_list(true).boxed(),
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use handle_errors::Error;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, TextEncoder, TEXT_FORMAT,
+};
+use warp::http::Method;
+use warp::path::FullPath;
+use warp::reply::Response;
+
+use crate::store::Store;
+
+pub const CONTENT_TYPE: &str = TEXT_FORMAT;
+
+static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "http_requests_total",
+        "Number of HTTP requests by route, method and status",
+        &["route", "method", "status"]
+    )
+    .unwrap()
+});
+
+static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "Time to answer HTTP requests by route, method and status",
+        &["route", "method", "status"]
+    )
+    .unwrap()
+});
+
+static DB_POOL_CONNECTIONS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "db_pool_connections",
+        "Connections held by the Postgres pool, by state",
+        &["state"]
+    )
+    .unwrap()
+});
+
+static PROFANITY_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "profanity_requests_total",
+        "Calls to the profanity API by outcome",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+static PROFANITY_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "profanity_request_duration_seconds",
+        "Duration of calls to the profanity API, including retries, by outcome",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+static PROFANITY_CACHE: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "profanity_cache_requests_total",
+        "Lookups in the profanity result cache, by hit or miss",
+        &["result"]
+    )
+    .unwrap()
+});
+
+static PROFANITY_CACHE_ENTRIES: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "profanity_cache_entries",
+        "Number of censored results held in the profanity cache"
+    )
+    .unwrap()
+});
+
+static PROFANITY_CIRCUIT_STATE: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "profanity_circuit_state",
+        "State of the profanity API circuit breaker: 0 closed, 1 open, 2 half-open"
+    )
+    .unwrap()
+});
+
+static PROFANITY_DEGRADED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "profanity_degraded_total",
+        "Texts handled without the profanity API while its circuit is open, by mode",
+        &["mode"]
+    )
+    .unwrap()
+});
+
+static MODERATION_JOBS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "moderation_jobs_total",
+        "Moderation job attempts by outcome",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+pub static QUESTIONS_CREATED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("questions_created_total", "Number of questions created").unwrap()
+});
+
+pub static ANSWERS_CREATED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("answers_created_total", "Number of answers created").unwrap()
+});
+
+/// Label of requests which no route answered: unknown paths and methods,
+/// CORS preflights, and requests refused by CORS before reaching a route
+pub const UNMATCHED: &str = "unmatched";
+
+/// Marks a response as answered before any route was matched, so the
+/// request is counted as `UNMATCHED` even if its path is a known one
+#[derive(Debug, Clone, Copy)]
+pub struct Unrouted;
+
+/// A documented route, by which requests are labelled
+struct Route {
+    method: Method,
+    /// `None` stands for a path parameter
+    segments: Vec<Option<String>>,
+    operation_id: String,
+}
+
+impl Route {
+    fn matches(&self, method: &Method, segments: &[&str]) -> bool {
+        self.method == method
+            && self.segments.len() == segments.len()
+            && self.segments.iter().zip(segments).all(|(expected, segment)| match expected {
+                Some(expected) => expected == segment,
+                None => !segment.is_empty(),
+            })
+    }
+}
+
+static ROUTES: LazyLock<Vec<Route>> = LazyLock::new(|| {
+    let mut routes = Vec::new();
+    for (path, item) in crate::openapi::spec().paths.paths {
+        let segments: Vec<Option<String>> = path
+            .split('/')
+            .map(|segment| (!segment.starts_with('{')).then(|| segment.to_string()))
+            .collect();
+        let operations = [
+            (Method::GET, item.get),
+            (Method::POST, item.post),
+            (Method::PUT, item.put),
+            (Method::DELETE, item.delete),
+        ];
+        for (method, operation) in operations {
+            if let Some(operation_id) = operation.and_then(|operation| operation.operation_id) {
+                routes.push(Route {
+                    method,
+                    segments: segments.clone(),
+                    operation_id,
+                });
+            }
+        }
+    }
+    routes
+});
+
+/// Record a request answered with `res`, `start` being when it came in
+pub fn record_request(method: Method, path: FullPath, start: Instant, res: Response) -> Response {
+    let route = match res.extensions().get::<Unrouted>() {
+        Some(_) => UNMATCHED,
+        None => route_label(&method, path.as_str()),
+    };
+    let status = res.status();
+    let labels = [route, method.as_str(), status.as_str()];
+
+    HTTP_REQUESTS.with_label_values(&labels).inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+    res
+}
+
+/// The operation id of the route `path` is served by, so the number of
+/// label values stays bounded whatever paths clients ask for
+fn route_label(method: &Method, path: &str) -> &'static str {
+    // The Swagger UI is a page rather than part of the API, so it isn't in the spec
+    if method == Method::GET && (path == "/docs" || path.starts_with("/docs/")) {
+        return "swagger_ui";
+    }
+
+    let segments: Vec<&str> = path.split('/').collect();
+    ROUTES
+        .iter()
+        .find(|route| route.matches(method, &segments))
+        .map_or(UNMATCHED, |route| route.operation_id.as_str())
+}
+
+pub fn record_profanity_call<T>(result: &Result<T, Error>, elapsed: Duration) {
+    let outcome = match result {
+        Ok(_) => "success",
+        Err(Error::ReqwestAPIError(_)) => "reqwest_api_error",
+        Err(Error::MiddlewareReqwestAPIError(_)) => "middleware_reqwest_api_error",
+        Err(Error::ClientError(_)) => "client_error",
+        Err(Error::ServerError(_)) => "server_error",
+        Err(_) => "other",
+    };
+
+    PROFANITY_REQUESTS.with_label_values(&[outcome]).inc();
+    PROFANITY_REQUEST_DURATION
+        .with_label_values(&[outcome])
+        .observe(elapsed.as_secs_f64());
+}
+
+pub fn record_profanity_cache(hit: bool) {
+    let result = if hit { "hit" } else { "miss" };
+    PROFANITY_CACHE.with_label_values(&[result]).inc();
+}
+
+pub fn set_profanity_cache_entries(entries: usize) {
+    PROFANITY_CACHE_ENTRIES.set(entries as i64);
+}
+
+pub fn set_profanity_circuit_state(state: i64) {
+    PROFANITY_CIRCUIT_STATE.set(state);
+}
+
+pub fn record_profanity_degraded(mode: &str) {
+    PROFANITY_DEGRADED.with_label_values(&[mode]).inc();
+}
+
+pub fn record_moderation_job(outcome: &str) {
+    MODERATION_JOBS.with_label_values(&[outcome]).inc();
+}
+
+/// Render all metrics in the Prometheus text format
+pub fn encode(store: &Store) -> Vec<u8> {
+    // Counters are registered on first use; make sure they show up as 0 before that
+    LazyLock::force(&QUESTIONS_CREATED);
+    LazyLock::force(&ANSWERS_CREATED);
+    LazyLock::force(&PROFANITY_CACHE_ENTRIES);
+    for result in ["hit", "miss"] {
+        PROFANITY_CACHE.with_label_values(&[result]);
+    }
+
+    let size = store.connection.size() as i64;
+    let idle = store.connection.num_idle() as i64;
+    DB_POOL_CONNECTIONS.with_label_values(&["idle"]).set(idle);
+    DB_POOL_CONNECTIONS.with_label_values(&["in_use"]).set(size - idle);
+
+    let mut buffer = Vec::new();
+    // Encoding only fails for malformed metric families, which the
+    // registration macros already rule out
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    buffer
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use sqlx::migrate::{Migrate, MigrateError, Migrator};
+
+use crate::store::Store;
+
+/// Migrations from the `migrations/` folder, embedded at compile time
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Apply all pending migrations
+pub async fn run(store: &Store) -> Result<(), MigrateError> {
+    MIGRATOR.run(&store.connection).await
+}
+
+/// Revert all applied migrations newer than `to`.
+/// Without a target only the most recently applied migration is reverted.
+pub async fn revert(store: &Store, to: Option<i64>) -> Result<(), MigrateError> {
+    let target = match to {
+        Some(version) => version,
+        None => {
+            let mut conn = store.connection.acquire().await?;
+            conn.ensure_migrations_table().await?;
+
+            let mut applied: Vec<i64> = conn
+                .list_applied_migrations()
+                .await?
+                .into_iter()
+                .map(|m| m.version)
+                .collect();
+            applied.sort_unstable();
+
+            match applied.len() {
+                0 => return Ok(()),
+                1 => 0,
+                n => applied[n - 2],
+            }
+        }
+    };
+
+    tracing::event!(tracing::Level::INFO, "Reverting migrations newer than {}", target);
+    MIGRATOR.undo(&store.connection, target).await
+}
+
+/// Number of known migrations which haven't been applied yet
+pub async fn pending(store: &Store) -> Result<usize, MigrateError> {
+    let mut conn = store.connection.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    let applied: Vec<i64> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| m.version)
+        .collect();
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .filter(|m| !applied.contains(&m.version))
+        .count())
+}
+
+/// Print every known migration together with its state in the database
+pub async fn status(store: &Store) -> Result<(), MigrateError> {
+    let mut conn = store.connection.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    if let Some(version) = conn.dirty_version().await? {
+        println!("Migration {} is partially applied (dirty)", version);
+    }
+
+    let applied: HashMap<i64, Vec<u8>> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m.checksum.into_owned()))
+        .collect();
+
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+    {
+        let state = match applied.get(&migration.version) {
+            Some(checksum) if *checksum == *migration.checksum => "applied",
+            Some(_) => "applied (checksum mismatch)",
+            None => "pending",
+        };
+        println!("{} {:<30} {}", migration.version, migration.description, state);
+    }
+
+    Ok(())
+}
+
+/// Create an empty `<timestamp>_<name>.up.sql` and `.down.sql` pair in `source`.
+/// Returns the paths of both files.
+pub fn create(source: &Path, name: &str) -> std::io::Result<(PathBuf, PathBuf)> {
+    let name = name.trim().replace(char::is_whitespace, "_");
+    let prefix = format!("{}_{}", chrono::Utc::now().format("%Y%m%d%H%M%S"), name);
+
+    std::fs::create_dir_all(source)?;
+
+    let up = source.join(format!("{}.up.sql", prefix));
+    let down = source.join(format!("{}.down.sql", prefix));
+    std::fs::write(&up, "-- Add up migration script here\n")?;
+    std::fs::write(&down, "-- Add down migration script here\n")?;
+
+    Ok((up, down))
+}
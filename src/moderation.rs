@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use handle_errors::Error;
+use tokio::task::JoinHandle;
+
+use crate::metrics;
+use crate::profanity::{Profanity, Review};
+use crate::shutdown::Readiness;
+use crate::store::Store;
+use crate::types::moderation::{ModerationJob, ModerationOutcome, ModerationTarget};
+
+/// How long to wait before looking for new jobs once the queue is empty
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a claimed job stays hidden from other workers
+const LEASE: Duration = Duration::from_secs(300);
+/// Delay before the first retry; doubled for every further attempt
+const RETRY_BASE: Duration = Duration::from_secs(5);
+const RETRY_MAX: Duration = Duration::from_secs(600);
+
+/// Start `count` workers checking queued content until shutdown starts
+pub fn spawn_workers(
+    count: usize,
+    store: Store,
+    profanity: Profanity,
+    readiness: Readiness,
+    max_attempts: i32,
+) -> Vec<JoinHandle<()>> {
+    (0..count)
+        .map(|_| {
+            let (store, profanity, readiness) = (store.clone(), profanity.clone(), readiness.clone());
+            tokio::spawn(async move {
+                while readiness.is_ready() {
+                    match store.claim_moderation_job(LEASE).await {
+                        Ok(Some(job)) => process(&store, &profanity, job, max_attempts).await,
+                        // Claiming errors are logged by the store; try again later
+                        Ok(None) | Err(_) => tokio::time::sleep(POLL_INTERVAL).await,
+                    }
+                }
+            })
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "moderation_job", skip_all, fields(job.id = job.id, job.attempts = job.attempts))]
+async fn process(store: &Store, profanity: &Profanity, job: ModerationJob, max_attempts: i32) {
+    let outcome = match profanity.review(&job.route, fields(&job.target)).await {
+        Ok(review) => Ok((decide(&review, &job.target), Some(review))),
+        Err(Error::ClientError(e)) if is_refused_content(e.status) => {
+            tracing::warn!("Profanity API refused the content, rejecting it: {}", e);
+            Ok((ModerationOutcome::Reject, None))
+        }
+        Err(e) => Err(e),
+    };
+
+    let result = match outcome {
+        Ok((outcome, review)) => {
+            metrics::record_moderation_job(match outcome {
+                ModerationOutcome::Approve { .. } => "approved",
+                ModerationOutcome::Reject => "rejected",
+            });
+            // The findings are audited along with the outcome, so a retry
+            // after a failed check doesn't audit the same text twice
+            store.finish_moderation_job(&job, outcome, review.as_ref()).await
+        }
+        Err(e) if job.attempts < max_attempts => {
+            let delay = RETRY_BASE
+                .saturating_mul(1 << (job.attempts - 1).clamp(0, 16))
+                .min(RETRY_MAX);
+            tracing::warn!("Moderation failed, retrying in {:?}: {}", delay, e);
+            metrics::record_moderation_job("retried");
+            store.fail_moderation_job(job.id, &e.to_string(), Some(delay)).await
+        }
+        Err(e) => {
+            tracing::error!("Moderation failed {} times, giving up: {}", job.attempts, e);
+            metrics::record_moderation_job("dead_lettered");
+            store.fail_moderation_job(job.id, &e.to_string(), None).await
+        }
+    };
+
+    // The lease runs out eventually, and the job is picked up again
+    if result.is_err() {
+        tracing::error!("Cannot record the result of moderation job {}", job.id);
+    }
+}
+
+/// The text of a job's target to check, by field
+fn fields(target: &ModerationTarget) -> Vec<(&'static str, String)> {
+    match target {
+        ModerationTarget::Question { title, content, .. } => {
+            vec![("title", title.clone()), ("content", content.clone())]
+        }
+        ModerationTarget::Answer { content, .. } => vec![("content", content.clone())],
+    }
+}
+
+/// What to do with a job's target, given how its text fared against the
+/// policies of the route it was submitted to
+fn decide(review: &Review, target: &ModerationTarget) -> ModerationOutcome {
+    if let Some(e) = review.rejection() {
+        tracing::info!("Rejecting content: {}", e);
+        return ModerationOutcome::Reject;
+    }
+
+    ModerationOutcome::Approve {
+        title: match target {
+            ModerationTarget::Question { .. } => Some(review.checked("title").content),
+            ModerationTarget::Answer { .. } => None,
+        },
+        content: review.checked("content").content,
+        needs_review: review.needs_review(),
+    }
+}
+
+/// Statuses the profanity API answers with when the text itself is the
+/// problem, as opposed to e.g. our API key or quota
+fn is_refused_content(status: u16) -> bool {
+    matches!(status, 400 | 413 | 422)
+}
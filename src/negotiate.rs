@@ -0,0 +1,201 @@
+use handle_errors::Error;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use warp::http::{header, Response};
+use warp::hyper::body::Bytes;
+use warp::{Filter, Rejection};
+
+pub const JSON: &str = "application/json";
+pub const FORM: &str = "application/x-www-form-urlencoded";
+pub const MSGPACK: &str = "application/msgpack";
+pub const CSV: &str = "text/csv";
+
+/// Media types request bodies are accepted in
+pub const BODY_TYPES: &[&str] = &[JSON, FORM, MSGPACK];
+/// Other names MessagePack goes by
+const MSGPACK_ALIASES: &[&str] = &["application/x-msgpack", "application/vnd.msgpack"];
+
+/// Formats a single value is available in
+pub const SINGLE: &[Format] = &[Format::Json, Format::MessagePack];
+/// Formats a list is available in
+pub const LIST: &[Format] = &[Format::Json, Format::MessagePack, Format::Csv];
+
+/// Deserialize a request body read by `bytes` according to its
+/// `Content-Type`. Bodies without one are taken to be JSON.
+pub fn body<T: DeserializeOwned + Send>(
+    bytes: impl Filter<Extract = (Bytes,), Error = Rejection> + Clone + Send,
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("content-type")
+        .and(bytes)
+        .and_then(|content_type: Option<String>, body: Bytes| async move {
+            decode(content_type.as_deref(), &body).map_err(warp::reject::custom)
+        })
+}
+
+fn decode<T: DeserializeOwned>(content_type: Option<&str>, body: &[u8]) -> Result<T, Error> {
+    let media_type = content_type.map(essence).unwrap_or_else(|| JSON.to_string());
+    let invalid = |e: &dyn std::fmt::Display| Error::InvalidBody(e.to_string());
+
+    match media_type.as_str() {
+        JSON => serde_json::from_slice(body).map_err(|e| invalid(&e)),
+        FORM => serde_html_form::from_bytes(body).map_err(|e| invalid(&e)),
+        t if t == MSGPACK || MSGPACK_ALIASES.contains(&t) => {
+            rmp_serde::from_slice(body).map_err(|e| invalid(&e))
+        }
+        _ => Err(Error::UnsupportedMediaType(media_type, BODY_TYPES.to_vec())),
+    }
+}
+
+/// The media type without parameters such as `charset`
+fn essence(value: &str) -> String {
+    value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// A representation of a response body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MessagePack,
+    /// One row per item of a list, with a header row of the field names.
+    /// Lists nested in items are joined with `;`.
+    Csv,
+}
+
+impl Format {
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Format::Json => JSON,
+            Format::MessagePack => MSGPACK,
+            Format::Csv => CSV,
+        }
+    }
+
+    fn matches(&self, media_type: &str) -> bool {
+        let own = self.media_type();
+        media_type == "*/*"
+            || media_type == own
+            || media_type
+                .strip_suffix("/*")
+                .is_some_and(|kind| own.starts_with(&format!("{}/", kind)))
+            || (*self == Format::MessagePack && MSGPACK_ALIASES.contains(&media_type))
+    }
+
+    /// Serialize `value` in this format
+    pub fn encode<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        // Our types only consist of strings, numbers and lists, which always serialize
+        match self {
+            Format::Json => serde_json::to_vec(value).unwrap(),
+            Format::MessagePack => rmp_serde::to_vec_named(value).unwrap(),
+            Format::Csv => to_csv(serde_json::to_value(value).unwrap()),
+        }
+    }
+
+    /// Serialize `value` into a response in this format
+    pub fn reply<T: Serialize>(&self, value: &T) -> warp::reply::Response {
+        Response::builder()
+            .header(header::CONTENT_TYPE, self.media_type())
+            .header(header::VARY, "accept")
+            .body(self.encode(value).into())
+            .unwrap()
+    }
+}
+
+/// Pick the format to respond in from those `available`, by the
+/// client's `Accept` header. Without one, the first format is used.
+pub fn accept(
+    available: &'static [Format],
+) -> impl Filter<Extract = (Format,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept").and_then(move |accept: Option<String>| async move {
+        match accept {
+            None => Ok(available[0]),
+            Some(accept) => choose(&accept, available).ok_or_else(|| {
+                warp::reject::custom(Error::NotAcceptable(
+                    available.iter().map(Format::media_type).collect(),
+                ))
+            }),
+        }
+    })
+}
+
+/// The available format with the highest quality in `accept`, which for each
+/// format is that of the most specific media range matching it. Ties go to
+/// the format listed first in `available`.
+fn choose(accept: &str, available: &[Format]) -> Option<Format> {
+    let ranges: Vec<(String, f32)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let media_type = essence(parts.next()?);
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!media_type.is_empty()).then_some((media_type, quality))
+        })
+        .collect();
+
+    let mut best: Option<(Format, f32)> = None;
+    for format in available {
+        let quality = ranges
+            .iter()
+            .filter(|(media_type, _)| format.matches(media_type))
+            .max_by_key(|(media_type, _)| specificity(media_type))
+            .map(|(_, quality)| *quality);
+
+        match (quality, best) {
+            (Some(quality), Some((_, best_quality))) if quality <= best_quality => {}
+            (Some(quality), _) if quality > 0.0 => best = Some((*format, quality)),
+            _ => {}
+        }
+    }
+    best.map(|(format, _)| format)
+}
+
+fn specificity(media_type: &str) -> u8 {
+    match media_type {
+        "*/*" => 0,
+        t if t.ends_with("/*") => 1,
+        _ => 2,
+    }
+}
+
+/// Write a list of objects as CSV, or a single object as one row
+fn to_csv(value: Value) -> Vec<u8> {
+    let rows = match value {
+        Value::Array(rows) => rows,
+        row => vec![row],
+    };
+    let header: Vec<String> = match rows.first() {
+        Some(Value::Object(first)) => first.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    // Writing to memory doesn't fail
+    if !header.is_empty() {
+        writer.write_record(&header).unwrap();
+    }
+    for row in &rows {
+        let cells: Vec<String> = match row {
+            Value::Object(fields) => header.iter().map(|name| cell(fields.get(name))).collect(),
+            other => vec![cell(Some(other))],
+        };
+        writer.write_record(&cells).unwrap();
+    }
+    writer.into_inner().unwrap()
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| cell(Some(item)))
+            .collect::<Vec<_>>()
+            .join(";"),
+        Some(other) => other.to_string(),
+    }
+}
@@ -0,0 +1,154 @@
+use utoipa::{Modify, OpenApi, ToResponse};
+use utoipa::openapi::path::PathItem;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::openapi::{Deprecated, OpenApi as Spec};
+
+use crate::routes;
+
+/// The OpenAPI document served at `/openapi.json`. Every route added to
+/// `routes::routes` has to be listed in `paths`, or in the `ApiDoc` of
+/// its version, or the drift test fails.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Q&A API",
+        description = "Questions and answers, checked for offensive words before they are stored."
+    ),
+    nest((path = "/v1", api = routes::v1::ApiDoc)),
+    paths(
+        routes::health::liveness,
+        routes::health::readiness,
+        routes::metrics::get_metrics,
+        routes::docs::openapi_json,
+    ),
+    tags(
+        (name = "questions"),
+        (name = "answers"),
+        (name = "admin", description = "Bulk import and export"),
+        (name = "events", description = "Changes to questions and answers, pushed over WebSockets"),
+        (name = "operations", description = "Health checks, metrics and this document")
+    ),
+    modifiers(&AdminToken)
+)]
+pub struct ApiDoc;
+
+/// The bearer token of `--admin-token`, required by the admin routes
+struct AdminToken;
+
+impl Modify for AdminToken {
+    fn modify(&self, spec: &mut Spec) {
+        spec.components.get_or_insert_with(Default::default).add_security_scheme(
+            "admin_token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// The document as served, including the deprecated unversioned
+/// aliases of `/v1`. utoipa takes the license from `Cargo.toml`, which
+/// doesn't name one, so the empty license is left out.
+pub fn spec() -> Spec {
+    let mut spec = ApiDoc::openapi();
+    spec.info.license = None;
+
+    let legacy: Vec<(String, PathItem)> = spec
+        .paths
+        .paths
+        .iter()
+        .filter_map(|(path, item)| Some((path.strip_prefix("/v1")?.to_string(), item.clone())))
+        .map(|(path, item)| (path, deprecated(item)))
+        .collect();
+    spec.paths.paths.extend(legacy);
+    spec
+}
+
+fn deprecated(mut item: PathItem) -> PathItem {
+    let operations = [&mut item.get, &mut item.post, &mut item.put, &mut item.delete];
+    for operation in operations.into_iter().flatten() {
+        operation.deprecated = Some(Deprecated::True);
+        operation.operation_id = operation.operation_id.take().map(|id| id + "_unversioned");
+    }
+    item
+}
+
+/// The request can't be handled as sent: a parameter or the body can't
+/// be parsed, or the database refused the query. Like all errors, the
+/// message ends with the request id.
+#[derive(ToResponse)]
+#[response(example = json!("Cannot parse parameter: invalid digit found in string (request id: 5b0e1f0c)"))]
+pub struct InvalidRequest(pub String);
+
+/// No approved question or answer has this id. Content queued for
+/// moderation is only found once a worker approved it.
+#[derive(ToResponse)]
+#[response(example = json!("Question 4711 not found (request id: 5b0e1f0c)"))]
+pub struct NotFound(pub String);
+
+/// The `/admin` routes need `Authorization: Bearer` with the token set by
+/// `--admin-token`, and refuse every request when none is set
+#[derive(ToResponse)]
+#[response(
+    headers(("www-authenticate" = String, description = "`Bearer`")),
+    example = json!("Missing or invalid admin token (request id: 5b0e1f0c)")
+)]
+pub struct Unauthorized(pub String);
+
+/// The body can't be parsed, a field is rejected by the profanity policy
+/// of the route, or the `Idempotency-Key` was used with another body
+#[derive(ToResponse)]
+#[response(example = json!("Content contains offensive words: title (damn); content (shit) (request id: 5b0e1f0c)"))]
+pub struct InvalidContent(pub String);
+
+/// The request body is larger than the route accepts after decompression.
+/// Limits are configured per route with `--body-limit`.
+#[derive(ToResponse)]
+#[response(example = json!("Request body exceeds the limit of 65536 bytes (request id: 5b0e1f0c)"))]
+pub struct PayloadTooLarge(pub String);
+
+/// The request body is in a media type or content encoding the route
+/// can't read. Bodies may be gzip-compressed.
+#[derive(ToResponse)]
+#[response(
+    headers(
+        ("accept" = String, description = "The media types request bodies can be sent in"),
+        ("accept-encoding" = String, description = "The content encodings request bodies can be sent in")
+    ),
+    example = json!("Unsupported content type `text/plain`, expected one of: application/json, application/x-www-form-urlencoded, application/msgpack (request id: 5b0e1f0c)")
+)]
+pub struct UnsupportedMediaType(pub String);
+
+/// None of the media types in the `Accept` header can be responded in
+#[derive(ToResponse)]
+#[response(example = json!("Cannot respond in any of the accepted media types, available are: application/json, application/msgpack (request id: 5b0e1f0c)"))]
+pub struct NotAcceptable(pub String);
+
+/// The `Idempotency-Key` header is empty or longer than 255 characters
+#[derive(ToResponse)]
+#[response(example = json!("Idempotency key must be between 1 and 255 characters (request id: 5b0e1f0c)"))]
+pub struct InvalidIdempotencyKey(pub String);
+
+/// A request with the same `Idempotency-Key` is still being handled
+#[derive(ToResponse)]
+#[response(example = json!("A request with this idempotency key is still being processed (request id: 5b0e1f0c)"))]
+pub struct IdempotencyKeyInUse(pub String);
+
+/// The client exceeded the rate limit of the route
+#[derive(ToResponse)]
+#[response(
+    headers(("retry-after" = u64, description = "Seconds until a request is let through again")),
+    example = json!("Too many requests, retry in 12s (request id: 5b0e1f0c)")
+)]
+pub struct TooManyRequests(pub String);
+
+/// The profanity API is down and content is refused until it is back
+#[derive(ToResponse)]
+#[response(
+    headers(("retry-after" = u64, description = "Seconds until the profanity API is tried again")),
+    example = json!("Service temporarily unavailable, retry in 30s (request id: 5b0e1f0c)")
+)]
+pub struct ServiceUnavailable(pub String);
+
+/// The profanity API failed to check the content
+#[derive(ToResponse)]
+#[response(example = json!("Internal Server Error (request id: 5b0e1f0c)"))]
+pub struct UpstreamError(pub String);
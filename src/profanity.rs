@@ -1,20 +1,63 @@
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use reqwest_middleware::ClientBuilder;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
+use sha2::{Digest, Sha256};
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::cli::{DegradedMode, ProfanityArgs};
+use crate::metrics;
+use crate::types::policy::Policy;
+
+type ContentHash = [u8; 32];
+
+/// Words censored by the local fallback filter, matched as whole words
+/// regardless of case. Deliberately short; it only has to hold the line
+/// until the profanity API is back.
+const LOCAL_WORDS: &[&str] = &[
+    "arse", "arsehole", "ass", "asshole", "bastard", "bitch", "bollocks", "bullshit", "crap",
+    "cunt", "damn", "dick", "fuck", "fucked", "fucking", "motherfucker", "piss", "prick",
+    "shit", "slut", "twat", "wanker", "whore",
+];
+
+/// Text to store after checking it for profanity
+#[derive(Debug, Clone)]
+pub struct Checked {
+    pub content: String,
+    /// The text is stored unchecked or uncensored and needs a moderator's review
+    pub needs_review: bool,
+}
+
+impl Checked {
+    /// Text taken as-is, for when checking was deliberately skipped
+    pub fn skipped(content: String) -> Self {
+        Checked {
+            content,
+            needs_review: false,
+        }
+    }
+}
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct APIResponse {
     message: String
 }
 
+/// An offensive word found in a text
 #[derive(Deserialize, Serialize, Debug, Clone)]
-struct BadWord {
-    original: String,
-    word: String,
-    deviations: i64,
-    info: i64,
+pub struct BadWord {
+    pub original: String,
+    pub word: String,
+    pub deviations: i64,
+    pub info: i64,
     #[serde(rename = "replacedLen")]
-    replaced_len: i64,
+    pub replaced_len: i64,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -25,41 +68,363 @@ struct BadWordsResponse {
     censored_content: String,
 }
 
+/// What was found in a text
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub original: String,
+    pub censored: String,
+    pub bad_words: Vec<BadWord>,
+    /// The text couldn't be checked, see `DegradedMode::Flag`
+    pub unchecked: bool,
+}
+
+impl Detection {
+    /// The text to store under `policy`. Rejected texts aren't stored,
+    /// so `Policy::Reject` gives the censored text like `Policy::Censor`.
+    pub fn apply(&self, policy: Policy) -> Checked {
+        if self.unchecked {
+            return Checked {
+                content: self.original.clone(),
+                needs_review: true,
+            };
+        }
+
+        match policy {
+            Policy::Censor | Policy::Reject => Checked {
+                content: self.censored.clone(),
+                needs_review: false,
+            },
+            Policy::Flag => Checked {
+                content: self.original.clone(),
+                needs_review: !self.bad_words.is_empty(),
+            },
+        }
+    }
+}
 
-pub async fn check_profanity(content: String) -> Result<String, handle_errors::Error> {
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-    let client = ClientBuilder::new(reqwest::Client::new())
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
+/// A checked field together with the policy configured for it
+#[derive(Debug, Clone)]
+pub struct FieldReview {
+    pub field: &'static str,
+    pub policy: Policy,
+    pub detection: Detection,
+}
 
-    let res = client
-        .post("https://api.apilayer.com/bad_words?censor_character=*")
-        .header("apikey", "sj7Ik9TUYAUlhs6oMuGzK4ErlMbc8Ske")
-        .body(content)
-        .send()
-        .await
-        .map_err(|e| handle_errors::Error::MiddlewareReqwestAPIError(e))?;
+impl FieldReview {
+    pub fn is_rejected(&self) -> bool {
+        self.policy == Policy::Reject && !self.detection.bad_words.is_empty()
+    }
+}
 
-    if !res.status().is_success() {
-        if res.status().is_client_error() {
-            let err = transform_error(res).await;
-            return Err(handle_errors::Error::ClientError(err));
+/// All fields of a submission to one route, checked for profanity
+#[derive(Debug, Clone)]
+pub struct Review {
+    pub route: String,
+    pub fields: Vec<FieldReview>,
+}
+
+impl Review {
+    /// The text to store for `field`, which has to be one of the reviewed fields
+    pub fn checked(&self, field: &str) -> Checked {
+        self.fields
+            .iter()
+            .find(|review| review.field == field)
+            .map(|review| review.detection.apply(review.policy))
+            .expect("field was not reviewed")
+    }
+
+    pub fn needs_review(&self) -> bool {
+        self.fields
+            .iter()
+            .any(|review| review.detection.apply(review.policy).needs_review)
+    }
+
+    /// The error to answer with if any field is rejected by its policy
+    pub fn rejection(&self) -> Option<handle_errors::Error> {
+        let fields: Vec<handle_errors::OffensiveField> = self
+            .fields
+            .iter()
+            .filter(|review| review.is_rejected())
+            .map(|review| handle_errors::OffensiveField {
+                field: review.field.to_string(),
+                words: review
+                    .detection
+                    .bad_words
+                    .iter()
+                    .map(|word| word.original.clone())
+                    .collect(),
+            })
+            .collect();
+
+        if fields.is_empty() {
+            None
         } else {
-            let err = transform_error(res).await;
-            return Err(handle_errors::Error::ServerError(err));
+            Some(handle_errors::Error::OffensiveContent(fields))
         }
     }
+}
+
+/// Client for the profanity API, meant to live as long as the application.
+/// Censored results are cached by a hash of the submitted text, so
+/// resubmitting unchanged text doesn't cost another API call.
+/// Calls go through a circuit breaker; while it is open, texts are
+/// handled according to the configured `DegradedMode`.
+/// What happens to offensive words depends on the `Policy` configured
+/// for each route and field, censoring them by default.
+#[derive(Clone)]
+pub struct Profanity {
+    base_url: Arc<str>,
+    client: ClientWithMiddleware,
+    cache: Option<Arc<Mutex<LruCache<ContentHash, CachedResult>>>>,
+    ttl: Duration,
+    breaker: Arc<CircuitBreaker>,
+    degraded_mode: DegradedMode,
+    policies: Arc<HashMap<(String, Option<String>), Policy>>,
+}
+
+struct CachedResult {
+    detection: Detection,
+    stored_at: Instant,
+}
+
+impl Profanity {
+    pub fn new(args: &ProfanityArgs) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(args.profanity_timeout))
+            .build()
+            .expect("Cannot build the profanity API client");
+        let retry_policy =
+            ExponentialBackoff::builder().build_with_max_retries(args.profanity_retries);
+        let client = ClientBuilder::new(http)
+            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .build();
+
+        Profanity {
+            base_url: args.profanity_api_url.trim_end_matches('/').into(),
+            client,
+            cache: NonZeroUsize::new(args.profanity_cache_size)
+                .map(|size| Arc::new(Mutex::new(LruCache::new(size)))),
+            ttl: Duration::from_secs(args.profanity_cache_ttl),
+            breaker: Arc::new(CircuitBreaker::new(
+                args.profanity_failure_threshold,
+                Duration::from_secs(args.profanity_cool_down),
+            )),
+            degraded_mode: args.profanity_degraded_mode,
+            policies: Arc::new(
+                args.profanity_policies
+                    .iter()
+                    .map(|rule| ((rule.route.clone(), rule.field.clone()), rule.policy))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The policy for `field` on `route`: a rule naming the field wins
+    /// over one for the whole route
+    pub fn policy(&self, route: &str, field: &str) -> Policy {
+        self.policies
+            .get(&(route.to_string(), Some(field.to_string())))
+            .or_else(|| self.policies.get(&(route.to_string(), None)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Check all `fields` of a submission to `route` concurrently
+    pub async fn review(
+        &self,
+        route: &str,
+        fields: Vec<(&'static str, String)>,
+    ) -> Result<Review, handle_errors::Error> {
+        let checks = fields.into_iter().map(|(field, content)| async move {
+            Ok::<_, handle_errors::Error>(FieldReview {
+                field,
+                policy: self.policy(route, field),
+                detection: self.detect(content).await?,
+            })
+        });
 
-    match res.json::<BadWordsResponse>()
-        .await {
-            Ok(res) => Ok(res.censored_content),
-            Err(e) => Err(handle_errors::Error::ReqwestAPIError(e)),
+        Ok(Review {
+            route: route.to_string(),
+            fields: futures::future::try_join_all(checks).await?,
+        })
+    }
+
+    #[tracing::instrument(name = "profanity_check", skip_all)]
+    pub async fn detect(&self, content: String) -> Result<Detection, handle_errors::Error> {
+        let key: ContentHash = Sha256::digest(content.as_bytes()).into();
+        if let Some(detection) = self.cached(&key) {
+            return Ok(detection);
+        }
+
+        if !self.breaker.allow() {
+            return self.degraded(content);
+        }
+
+        let start = Instant::now();
+        let res = self.censor(content).await;
+        metrics::record_profanity_call(&res, start.elapsed());
+        self.breaker.record(!is_outage(&res));
+
+        if let (Ok(detection), Some(cache)) = (&res, &self.cache) {
+            let mut cache = cache.lock();
+            cache.put(
+                key,
+                CachedResult {
+                    detection: detection.clone(),
+                    stored_at: Instant::now(),
+                },
+            );
+            metrics::set_profanity_cache_entries(cache.len());
+        }
+        res
+    }
+
+    fn degraded(&self, content: String) -> Result<Detection, handle_errors::Error> {
+        match self.degraded_mode {
+            DegradedMode::Reject => {
+                metrics::record_profanity_degraded("reject");
+                Err(handle_errors::Error::ServiceUnavailable(self.breaker.retry_after()))
+            }
+            DegradedMode::Flag => {
+                metrics::record_profanity_degraded("flag");
+                Ok(Detection {
+                    original: content.clone(),
+                    censored: content,
+                    bad_words: Vec::new(),
+                    unchecked: true,
+                })
+            }
+            DegradedMode::LocalFilter => {
+                metrics::record_profanity_degraded("local_filter");
+                Ok(detect_locally(content))
+            }
+        }
+    }
+
+    fn cached(&self, key: &ContentHash) -> Option<Detection> {
+        let mut cache = self.cache.as_ref()?.lock();
+
+        let hit = match cache.get(key) {
+            Some(entry) if entry.stored_at.elapsed() < self.ttl => Some(entry.detection.clone()),
+            Some(_) => {
+                cache.pop(key);
+                metrics::set_profanity_cache_entries(cache.len());
+                None
+            }
+            None => None,
+        };
+        metrics::record_profanity_cache(hit.is_some());
+        hit
+    }
+
+    async fn censor(&self, content: String) -> Result<Detection, handle_errors::Error> {
+        let mut req = self
+            .client
+            .post(format!("{}/bad_words?censor_character=*", self.base_url))
+            .header("apikey", "sj7Ik9TUYAUlhs6oMuGzK4ErlMbc8Ske")
+            .headers(crate::telemetry::trace_headers());
+        if let Some(id) = handle_errors::current_request_id() {
+            req = req.header(crate::request_id::HEADER, id);
+        }
+
+        let res = req
+            .body(content.clone())
+            .send()
+            .await
+            .map_err(handle_errors::Error::MiddlewareReqwestAPIError)?;
+
+        if !res.status().is_success() {
+            if res.status().is_client_error() {
+                let err = transform_error(res).await;
+                return Err(handle_errors::Error::ClientError(err));
+            } else {
+                let err = transform_error(res).await;
+                return Err(handle_errors::Error::ServerError(err));
+            }
+        }
+
+        match res.json::<BadWordsResponse>()
+            .await {
+                Ok(res) => Ok(Detection {
+                    original: content,
+                    censored: res.censored_content,
+                    bad_words: res.bad_words_list,
+                    unchecked: false,
+                }),
+                Err(e) => Err(handle_errors::Error::ReqwestAPIError(e)),
+        }
+    }
+
+    /// Seconds until the profanity API is called again, while the circuit
+    /// breaker holds calls back after repeated failures
+    pub fn unavailable_for(&self) -> Option<u64> {
+        self.breaker.is_open().then(|| self.breaker.retry_after())
     }
 }
 
-async fn transform_error(res: reqwest::Response) -> handle_errors::APILayerError {
-    handle_errors::APILayerError {
-        status: res.status().as_u16(),
-        message: res.json::<APIResponse>().await.unwrap().message,
+/// Whether a failed call means the profanity API itself is in trouble,
+/// as opposed to it rejecting this particular text. A refused API key
+/// (401) or an exhausted quota (429) fails every text alike.
+fn is_outage<T>(res: &Result<T, handle_errors::Error>) -> bool {
+    matches!(
+        res,
+        Err(handle_errors::Error::ReqwestAPIError(_))
+            | Err(handle_errors::Error::MiddlewareReqwestAPIError(_))
+            | Err(handle_errors::Error::ServerError(_))
+            | Err(handle_errors::Error::ClientError(handle_errors::APILayerError {
+                status: 401 | 429,
+                ..
+            }))
+    )
+}
+
+/// Look for words in `LOCAL_WORDS`, censoring them by replacing every character with `*`
+fn detect_locally(content: String) -> Detection {
+    let mut censored = String::with_capacity(content.len());
+    let mut bad_words = Vec::new();
+    let mut rest = content.as_str();
+
+    while let Some(start) = rest.find(char::is_alphanumeric) {
+        censored.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len());
+        let word = &rest[..end];
+
+        let lowercase = word.to_lowercase();
+        if LOCAL_WORDS.contains(&lowercase.as_str()) {
+            let len = word.chars().count();
+            censored.extend(std::iter::repeat_n('*', len));
+            bad_words.push(BadWord {
+                original: word.to_string(),
+                word: lowercase,
+                deviations: 0,
+                info: 0,
+                replaced_len: len as i64,
+            });
+        } else {
+            censored.push_str(word);
+        }
+        rest = &rest[end..];
     }
-}
\ No newline at end of file
+    censored.push_str(rest);
+
+    Detection {
+        original: content,
+        censored,
+        bad_words,
+        unchecked: false,
+    }
+}
+
+/// Read the error APILayer answered with, falling back to the raw body
+/// for responses that don't carry an `APIResponse`
+async fn transform_error(res: reqwest::Response) -> handle_errors::APILayerError {
+    let status = res.status().as_u16();
+    let body = res.text().await.unwrap_or_default();
+    let message = match serde_json::from_str::<APIResponse>(&body) {
+        Ok(res) => res.message,
+        Err(_) => body,
+    };
+
+    handle_errors::APILayerError { status, message }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use handle_errors::Error;
+use lru::LruCache;
+use parking_lot::Mutex;
+use tokio_util::sync::CancellationToken;
+use warp::http::HeaderMap;
+use warp::{Filter, Rejection, Reply};
+
+use crate::store::Store;
+use crate::types::network::IpNetwork;
+use crate::types::rate_limit::{Limit, Quota, RateLimitRule};
+
+/// Address of the connected client, added to each request's extensions
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub SocketAddr);
+
+/// Buckets kept in memory. Past this, the least recently used one is
+/// dropped, which is most likely full again anyway.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// IPv6 clients usually get a whole /64, so they are limited by it
+const IPV6_CLIENT_PREFIX: u8 = 64;
+
+/// How often the buckets in the database are swept
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket rate limiter keyed by route and client IP.
+/// Buckets live in memory, or in Postgres when they have to be
+/// shared between several instances.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    limits: Arc<HashMap<String, Limit>>,
+    buckets: Arc<Mutex<LruCache<(&'static str, IpAddr), Bucket>>>,
+    trusted_proxies: Arc<[IpNetwork]>,
+    shared: Option<Store>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        rules: Vec<RateLimitRule>,
+        trusted_proxies: Vec<IpNetwork>,
+        shared: Option<Store>,
+    ) -> Self {
+        let capacity = NonZeroUsize::new(MAX_TRACKED_CLIENTS).unwrap();
+        RateLimiter {
+            limits: Arc::new(rules.into_iter().map(|r| (r.route, r.limit)).collect()),
+            buckets: Arc::new(Mutex::new(LruCache::new(capacity))),
+            trusted_proxies: trusted_proxies.into(),
+            shared,
+        }
+    }
+
+    /// Take a token for `route`, rejecting with `Error::TooManyRequests`
+    /// once the client's bucket is empty. Extracts the remaining quota, or
+    /// `None` if no limit is configured for the route.
+    /// Must come after enough of the method and path filters to tell
+    /// routes apart, so only requests meant for `route` are counted.
+    pub fn check(
+        &self,
+        route: &'static str,
+    ) -> impl Filter<Extract = (Option<Quota>,), Error = Rejection> + Clone {
+        let limiter = self.clone();
+        client(self.trusted_proxies.clone()).and_then(move |client: Option<IpAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                match limiter.take(route, client).await {
+                    Ok(quota) => Ok(quota),
+                    Err(retry_after) => Err(warp::reject::custom(Error::TooManyRequests(retry_after))),
+                }
+            }
+        })
+    }
+
+    async fn take(&self, route: &'static str, client: Option<IpAddr>) -> Result<Option<Quota>, u64> {
+        let (limit, addr) = match (self.limits.get(route), client) {
+            (Some(limit), Some(client)) => (*limit, client_key(client)),
+            _ => return Ok(None),
+        };
+
+        if let Some(store) = &self.shared {
+            return match store.take_rate_limit_token(route, &addr.to_string(), limit).await {
+                Ok(decision) => decision.map(Some),
+                // Rather let requests through than fail them all
+                // while the database is unavailable
+                Err(_) => Ok(None),
+            };
+        }
+
+        let mut buckets = self.buckets.lock();
+        let now = Instant::now();
+
+        let bucket = buckets.get_or_insert_mut((route, addr), || Bucket {
+            tokens: limit.capacity as f64,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        let (decision, tokens) = limit.take(bucket.tokens, elapsed);
+        bucket.tokens = tokens;
+        bucket.updated = now;
+
+        decision.map(Some)
+    }
+}
+
+/// The address of the client a request comes from, if known. Requests
+/// from `trusted_proxies` are taken to come from the nearest address in
+/// `X-Forwarded-For` which isn't one of them.
+pub fn client(
+    trusted_proxies: Arc<[IpNetwork]>,
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+    warp::ext::optional::<ClientAddr>()
+        .and(warp::header::headers_cloned())
+        .map(move |addr: Option<ClientAddr>, headers: HeaderMap| {
+            let addr = addr?.0.ip().to_canonical();
+            let trusted = |addr: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(*addr));
+            if !trusted(&addr) {
+                return Some(addr);
+            }
+
+            // Every proxy appends the address it was connected from, so the
+            // list is walked from the end. Anything before the first address
+            // not of a trusted proxy may be made up by the client.
+            let mut forwarded = headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .map(|hop| hop.trim().parse::<IpAddr>().map(|hop| hop.to_canonical()))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev();
+            let mut client = addr;
+            while trusted(&client) {
+                match forwarded.next() {
+                    Some(Ok(hop)) => client = hop,
+                    // Without a valid address, the last proxy is the best guess
+                    _ => break,
+                }
+            }
+            Some(client)
+        })
+}
+
+/// What a client's bucket is keyed by
+fn client_key(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V4(_) => addr,
+        IpAddr::V6(_) => IpNetwork::of(addr, IPV6_CLIENT_PREFIX).addr(),
+    }
+}
+
+/// Delete the buckets in the database which have been idle long enough
+/// to be full again, and so hold nothing a new bucket wouldn't, until
+/// `stop` is cancelled
+pub fn sweep(
+    store: Store,
+    rules: Vec<RateLimitRule>,
+    stop: CancellationToken,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = stop.cancelled() => return,
+                _ = ticks.tick() => {}
+            }
+            // Failures are logged by the store, and retried on the next tick
+            let _ = store.sweep_rate_limit_buckets(&rules).await;
+        }
+    })
+}
+
+/// Add `RateLimit-*` headers describing the remaining quota to a reply
+pub fn with_headers(quota: Option<Quota>, reply: impl Reply) -> warp::reply::Response {
+    let mut res = reply.into_response();
+    if let Some(quota) = quota {
+        let headers = res.headers_mut();
+        headers.insert("ratelimit-limit", quota.limit.into());
+        headers.insert("ratelimit-remaining", quota.remaining.into());
+        headers.insert("ratelimit-reset", quota.reset.into());
+    }
+    res
+}
@@ -0,0 +1,56 @@
+use std::convert::Infallible;
+
+use handle_errors::REQUEST_ID;
+use tracing::Instrument;
+use warp::http::{HeaderValue, Request, Response};
+use warp::hyper::{service::Service, Body};
+
+pub const HEADER: &str = "x-request-id";
+
+/// Longest incoming request id we accept before generating our own
+const MAX_LEN: usize = 128;
+
+/// Handle a request with `service`, correlated by a request id.
+/// The id is taken from the `X-Request-Id` header if the client sent a
+/// sane one, otherwise a new one is generated. It is attached to a span
+/// around the whole request, available to handlers and `return_error`
+/// through [`handle_errors::current_request_id`], and echoed back in the
+/// response headers.
+pub async fn handle<S>(mut service: S, req: Request<Body>) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let id = req
+        .headers()
+        .get(HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let span = tracing::info_span!(
+        "http",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.uri().path(),
+    );
+    crate::telemetry::set_remote_parent(&span, req.headers());
+
+    let mut res = REQUEST_ID
+        .scope(id.clone(), service.call(req))
+        .instrument(span)
+        .await?;
+
+    // Only visible ASCII passes `is_valid`, and generated ids are UUIDs
+    res.headers_mut()
+        .insert(HEADER, HeaderValue::from_str(&id).unwrap());
+    Ok(res)
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
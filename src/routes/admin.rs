@@ -0,0 +1,162 @@
+use futures::{StreamExt, TryStreamExt};
+use handle_errors::Error;
+use warp::{
+    http::{header, Response},
+    hyper::Body,
+};
+
+use crate::body::BodyStream;
+use crate::store::Store;
+use crate::types::bulk::{ImportLineError, ImportParams, ImportReport, QuestionRecord};
+
+/// Number of questions read from the database per export round trip
+const EXPORT_PAGE_SIZE: i64 = 100;
+/// Number of import lines written per transaction
+const IMPORT_BATCH_SIZE: usize = 100;
+
+/// Stream all questions with their answers as JSON Lines,
+/// one question per line, ordered by id
+#[utoipa::path(
+    get,
+    path = "/admin/export",
+    tag = "admin",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "One `QuestionRecord` per line",
+            body = QuestionRecord, content_type = "application/x-ndjson"),
+        (status = 401, response = crate::openapi::Unauthorized),
+    )
+)]
+pub async fn export(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+    let pages = futures::stream::try_unfold(Some(0), move |after| {
+        let store = store.clone();
+        async move {
+            let after = match after {
+                Some(after) => after,
+                None => return Ok(None),
+            };
+
+            let page = store.export_page(after, EXPORT_PAGE_SIZE).await?;
+            let next = match page.last() {
+                Some(last) if page.len() as i64 == EXPORT_PAGE_SIZE => last.id,
+                _ => None,
+            };
+
+            let mut chunk = String::new();
+            for record in page {
+                // A record only consists of strings and numbers, which always serialize
+                chunk.push_str(&serde_json::to_string(&record).unwrap());
+                chunk.push('\n');
+            }
+
+            Ok(Some((chunk, next)))
+        }
+    })
+    .map_err(|e: Error| {
+        tracing::event!(tracing::Level::ERROR, "Export aborted: {}", e);
+        std::io::Error::other(e.to_string())
+    });
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(Body::wrap_stream(pages)))
+}
+
+/// Read questions in the export format line by line and write them in
+/// batches. Lines which can't be parsed or stored are listed in the
+/// returned report instead of failing the whole import.
+///
+/// Content keeps the moderation status and review flag it was exported
+/// with, and content still pending is queued for moderation again.
+/// Records without a status are stored as approved, without profanity
+/// checks, which is why the route needs the admin token.
+#[utoipa::path(
+    post,
+    path = "/admin/import",
+    tag = "admin",
+    security(("admin_token" = [])),
+    params(ImportParams),
+    request_body(content = QuestionRecord, content_type = "application/x-ndjson",
+        description = "One `QuestionRecord` per line, as exported"),
+    responses(
+        (status = 200, description = "What was imported, and which lines were not", body = ImportReport),
+        (status = 401, response = crate::openapi::Unauthorized),
+        (status = 413, response = crate::openapi::PayloadTooLarge),
+        (status = 415, response = crate::openapi::UnsupportedMediaType),
+        (status = 422, response = crate::openapi::InvalidRequest),
+    )
+)]
+pub async fn import(
+    params: ImportParams,
+    store: Store,
+    body: BodyStream,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut report = ImportReport {
+        dry_run: params.dry_run,
+        ..ImportReport::default()
+    };
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut line = 0;
+
+    let mut body = body;
+    let mut body_done = false;
+
+    while !body_done {
+        match body.next().await {
+            Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+            Some(Err(Error::InvalidBody(e))) => {
+                report.errors.push(ImportLineError {
+                    line: line + 1,
+                    error: format!("Cannot read request body: {}", e),
+                });
+                buffer.clear();
+                body_done = true;
+            }
+            // The body exceeded its limit. Batches written so far stay imported.
+            Some(Err(e)) => return Err(warp::reject::custom(e)),
+            None => {
+                // The last line doesn't need a trailing newline
+                if !buffer.is_empty() {
+                    buffer.push(b'\n');
+                }
+                body_done = true;
+            }
+        }
+
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = buffer.drain(..=pos).collect();
+            line += 1;
+
+            if raw.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            match serde_json::from_slice::<QuestionRecord>(&raw) {
+                Ok(record) => batch.push((line, record)),
+                Err(e) => report.errors.push(ImportLineError {
+                    line,
+                    error: e.to_string(),
+                }),
+            }
+
+            if batch.len() == IMPORT_BATCH_SIZE {
+                let records = std::mem::replace(&mut batch, Vec::with_capacity(IMPORT_BATCH_SIZE));
+                match store.import_questions(records, params.dry_run).await {
+                    Ok(res) => report.merge(res),
+                    Err(e) => return Err(warp::reject::custom(e)),
+                }
+            }
+        }
+    }
+
+    if !batch.is_empty() {
+        match store.import_questions(batch, params.dry_run).await {
+            Ok(res) => report.merge(res),
+            Err(e) => return Err(warp::reject::custom(e)),
+        }
+    }
+
+    report.errors.sort_by_key(|e| e.line);
+    Ok(warp::reply::json(&report))
+}
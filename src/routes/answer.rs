@@ -1,31 +1,144 @@
-use warp::hyper::StatusCode;
+use handle_errors::Error;
 
 use crate::{
+    cli::ModerationMode,
+    idempotency::{self, IdempotencyKey},
+    metrics,
+    negotiate::Format,
     store::Store,
-    types::answer::NewAnswer, profanity::check_profanity,
+    types::{
+        answer::{Answer, AnswerId, NewAnswer},
+        idempotency::Recorded,
+    },
+    profanity::Profanity,
 };
 
+/// A single approved answer
+#[utoipa::path(
+    get,
+    path = "/answers/{id}",
+    tag = "answers",
+    params(("id" = i32, Path, description = "Id of the answer")),
+    responses(
+        (status = 200, description = "The answer", content(
+            (Answer = "application/json"),
+            (Answer = "application/msgpack"),
+        )),
+        (status = 404, response = crate::openapi::NotFound),
+        (status = 406, response = crate::openapi::NotAcceptable),
+        (status = 429, response = crate::openapi::TooManyRequests),
+    )
+)]
+pub async fn get_answer(
+    id: i32,
+    format: Format,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_answer(id).await? {
+        Some(answer) => Ok(format.reply(&answer)),
+        None => Err(warp::reject::custom(Error::AnswerNotFound(id))),
+    }
+}
+
 // TODO:
 // Create a random, unique ID instead of the one by hand
 // Add error handling if the fields we require aren't present
 // Check if a question exists
 // Change route to answers: /questions/:questionId/answers
+/// Answer a question. Offensive words are handled according to the
+/// policies of `add_answer`. Retries sent with the same
+/// `Idempotency-Key` get the original response.
+#[utoipa::path(
+    post,
+    path = "/answers",
+    tag = "answers",
+    params(("Idempotency-Key" = Option<String>, Header,
+        description = "Replay the response to an earlier request with this key instead of adding the answer again")),
+    request_body(content(
+        (NewAnswer = "application/x-www-form-urlencoded"),
+        (NewAnswer = "application/json"),
+        (NewAnswer = "application/msgpack"),
+    )),
+    responses(
+        (status = 201, description = "The stored answer",
+            headers(("location" = String, description = "Path of the answer")),
+            content((Answer = "application/json"), (Answer = "application/msgpack"))),
+        (status = 202, description = "The submitted answer, queued for moderation",
+            headers(("location" = String, description = "Path of the answer")),
+            content((Answer = "application/json"), (Answer = "application/msgpack"))),
+        (status = 400, response = crate::openapi::InvalidIdempotencyKey),
+        (status = 406, response = crate::openapi::NotAcceptable),
+        (status = 409, response = crate::openapi::IdempotencyKeyInUse),
+        (status = 413, response = crate::openapi::PayloadTooLarge),
+        (status = 415, response = crate::openapi::UnsupportedMediaType),
+        (status = 422, response = crate::openapi::InvalidContent),
+        (status = 429, response = crate::openapi::TooManyRequests),
+        (status = 500, response = crate::openapi::UpstreamError),
+        (status = 503, response = crate::openapi::ServiceUnavailable),
+    )
+)]
 pub async fn add_answer(
     store: Store,
+    profanity: Profanity,
+    moderation: ModerationMode,
+    key: Option<IdempotencyKey>,
+    format: Format,
     new_answer: NewAnswer,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let content = match check_profanity(new_answer.content).await {
-        Ok(res) => res,
-        Err(e) => return Err(warp::reject::custom(e)),
-    };
+    let fingerprint = idempotency::fingerprint(format, &new_answer);
+    // Boxed, as the filter chain's future would get too big for the stack otherwise
+    idempotency::once(
+        key,
+        fingerprint,
+        Box::pin(create_answer(store, profanity, moderation, format, new_answer)),
+    )
+    .await
+}
+
+async fn create_answer(
+    store: Store,
+    profanity: Profanity,
+    moderation: ModerationMode,
+    format: Format,
+    new_answer: NewAnswer,
+) -> Result<Recorded, warp::Rejection> {
+    if moderation == ModerationMode::Async {
+        let answer = store.queue_answer(new_answer).await?;
+        metrics::ANSWERS_CREATED.inc();
+        return Ok(Recorded::accepted(
+            format.media_type(),
+            location(&answer.id),
+            format.encode(&answer),
+        ));
+    }
+
+    let review = profanity
+        .review("add_answer", vec![("content", new_answer.content)])
+        .await?;
+    if let Some(e) = review.rejection() {
+        store.audit_profanity(&review).await?;
+        return Err(warp::reject::custom(e));
+    }
 
     let answer = NewAnswer {
-        content,
+        content: review.checked("content").content,
         question_id: new_answer.question_id,
     };
 
-    match store.add_answer(answer).await {
-        Ok(_) => Ok(warp::reply::with_status("Answer added", StatusCode::OK)),
+    match store.add_answer(answer, review.needs_review(), Some(&review)).await {
+        Ok(answer) => {
+            metrics::ANSWERS_CREATED.inc();
+            Ok(Recorded::created(
+                format.media_type(),
+                location(&answer.id),
+                format.encode(&answer),
+            ))
+        }
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
+
+/// Path of an answer in the current version of the API
+fn location(id: &AnswerId) -> String {
+    format!("/v1/answers/{}", id.0)
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use utoipa_swagger_ui::Config;
+use warp::http::{header, Response, StatusCode, Uri};
+use warp::path::{FullPath, Tail};
+use warp::Reply;
+
+use crate::openapi;
+
+/// Where the Swagger UI loads the specification from
+pub const SPEC_PATH: &str = "/openapi.json";
+
+/// This document
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "operations",
+    responses((status = 200, description = "The OpenAPI 3.1 document of this API"))
+)]
+pub async fn openapi_json() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&openapi::spec()))
+}
+
+/// Swagger UI for the specification, served from the files bundled
+/// into the binary so it works without internet access
+pub async fn swagger_ui(
+    full_path: FullPath,
+    tail: Tail,
+    config: Arc<Config<'static>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    // Relative asset paths only resolve below `/docs/`
+    if full_path.as_str() == "/docs" {
+        return Ok(warp::redirect::found(Uri::from_static("/docs/")).into_response());
+    }
+
+    match utoipa_swagger_ui::serve(tail.as_str(), config) {
+        Ok(Some(file)) => Ok(Response::builder()
+            .header(header::CONTENT_TYPE, file.content_type)
+            .body(file.bytes.into_owned().into())
+            .unwrap()),
+        Ok(None) => Err(warp::reject::not_found()),
+        Err(e) => {
+            tracing::event!(tracing::Level::ERROR, "Cannot serve Swagger UI: {}", e);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use handle_errors::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use warp::ws::{Message, WebSocket, Ws};
+
+use crate::events::Events;
+use crate::shutdown::Readiness;
+use crate::types::event::{ClientMessage, Event, ServerMessage, Topic};
+
+/// How often connections are pinged, so proxies don't close idle ones
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// Most topics one connection may be subscribed to at once
+const MAX_TOPICS: usize = 100;
+/// Largest message accepted from clients, in bytes
+const MAX_MESSAGE_SIZE: usize = 16 * 1024;
+
+/// Push question and answer events over a WebSocket. Clients send
+/// `ClientMessage`s to subscribe to topics, and are sent the `Event`s
+/// matching any of them, besides `ServerMessage`s, all as JSON text.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "events",
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol. \
+            Messages are `ClientMessage`s one way, `Event`s and `ServerMessage`s the other",
+            body = Event),
+        (status = 426, description = "Not a WebSocket handshake"),
+        (status = 429, response = crate::openapi::TooManyRequests),
+    )
+)]
+pub async fn subscribe(
+    ws: Option<Ws>,
+    events: Events,
+    readiness: Readiness,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ws = ws.ok_or_else(|| warp::reject::custom(Error::WebSocketRequired))?;
+    Ok(ws
+        .max_message_size(MAX_MESSAGE_SIZE)
+        .on_upgrade(move |socket| session(socket, events.subscribe(), readiness)))
+}
+
+async fn session(socket: WebSocket, mut events: Receiver<Event>, readiness: Readiness) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut topics = HashSet::new();
+    let mut ping = tokio::time::interval_at(
+        tokio::time::Instant::now() + PING_INTERVAL,
+        PING_INTERVAL,
+    );
+
+    loop {
+        let message = tokio::select! {
+            _ = readiness.stopping() => {
+                let _ = sender.send(Message::close_with(1001u16, "Server is shutting down")).await;
+                return;
+            }
+            _ = ping.tick() => Message::ping(Vec::new()),
+            received = receiver.next() => match received {
+                Some(Ok(message)) if message.is_close() => return,
+                Some(Ok(message)) => match message.to_str() {
+                    Ok(text) => handle(&mut topics, text),
+                    // Pings are answered already, pongs need no answer
+                    Err(()) if message.is_ping() || message.is_pong() => continue,
+                    Err(()) => reply(ServerMessage::Error {
+                        message: "Only JSON text messages are understood".to_string(),
+                    }),
+                },
+                Some(Err(e)) => {
+                    tracing::event!(tracing::Level::DEBUG, "WebSocket closed: {}", e);
+                    return;
+                }
+                None => return,
+            },
+            event = events.recv() => match event {
+                Ok(event) if topics.iter().any(|topic: &Topic| topic.matches(&event)) => {
+                    // Only consists of strings and numbers, which always serialize
+                    Message::text(serde_json::to_string(&event).unwrap())
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => reply(ServerMessage::Lagged { missed }),
+                Err(RecvError::Closed) => return,
+            },
+        };
+
+        if sender.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Apply a client's message to its `topics`
+fn handle(topics: &mut HashSet<Topic>, text: &str) -> Message {
+    reply(match serde_json::from_str(text) {
+        Ok(ClientMessage::Subscribe { topic }) => {
+            if topics.len() >= MAX_TOPICS && !topics.contains(&topic) {
+                ServerMessage::Error {
+                    message: format!("Cannot subscribe to more than {} topics", MAX_TOPICS),
+                }
+            } else {
+                topics.insert(topic.clone());
+                ServerMessage::Subscribed { topic }
+            }
+        }
+        Ok(ClientMessage::Unsubscribe { topic }) => {
+            topics.remove(&topic);
+            ServerMessage::Unsubscribed { topic }
+        }
+        Err(e) => ServerMessage::Error {
+            message: format!("Cannot read message: {}", e),
+        },
+    })
+}
+
+fn reply(message: ServerMessage) -> Message {
+    Message::text(serde_json::to_string(&message).unwrap())
+}
@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use warp::hyper::StatusCode;
+
+use crate::profanity::Profanity;
+use crate::shutdown::Readiness;
+use crate::store::Store;
+use crate::types::health::{Check, HealthReport, Status};
+
+/// How long a single dependency may take to answer a readiness probe
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The process is up and able to answer requests
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "operations",
+    responses((status = 200, description = "The process is up", body = HealthReport))
+)]
+pub async fn liveness() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&HealthReport {
+        status: Status::Up,
+        checks: BTreeMap::new(),
+    }))
+}
+
+/// The service can do useful work: it isn't shutting down, the database
+/// answers and was fully migrated at startup. A profanity API which the
+/// circuit breaker stopped calling only degrades the service, since reads
+/// still work without it. Probes never call the profanity API themselves.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Up, or degraded while the profanity API is failing",
+            body = HealthReport),
+        (status = 503, description = "Shutting down, or a required dependency is down",
+            body = HealthReport),
+    )
+)]
+pub async fn readiness(
+    readiness: Readiness,
+    store: Store,
+    profanity: Profanity,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut checks = BTreeMap::new();
+
+    if !readiness.is_ready() {
+        checks.insert(
+            "server",
+            Check {
+                status: Status::Down,
+                latency_ms: 0.0,
+                error: Some("Shutting down".to_string()),
+            },
+        );
+    }
+
+    checks.insert("database", check_database(&store).await);
+    if let Some(migrations) = check_migrations(&readiness) {
+        checks.insert("migrations", migrations);
+    }
+    checks.insert("profanity", check_profanity(&profanity));
+
+    let status = if checks.values().any(|c| c.status == Status::Down) {
+        Status::Down
+    } else if checks.values().any(|c| c.status == Status::Degraded) {
+        Status::Degraded
+    } else {
+        Status::Up
+    };
+    let code = match status {
+        Status::Down => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&HealthReport { status, checks }),
+        code,
+    ))
+}
+
+async fn check_database(store: &Store) -> Check {
+    let start = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, async {
+        let mut conn = store.connection.acquire().await?;
+        sqlx::query("SELECT 1").execute(&mut conn).await
+    })
+    .await;
+
+    match result {
+        Ok(Ok(_)) => check(Status::Up, start, None),
+        Ok(Err(e)) => check(Status::Down, start, Some(e.to_string())),
+        Err(_) => check(Status::Down, start, Some("Timed out".to_string())),
+    }
+}
+
+fn check_migrations(readiness: &Readiness) -> Option<Check> {
+    let start = Instant::now();
+
+    Some(match readiness.pending_migrations()? {
+        Ok(0) => check(Status::Up, start, None),
+        Ok(n) => check(Status::Down, start, Some(format!("{} pending migrations", n))),
+        Err(e) => check(Status::Down, start, Some(e.clone())),
+    })
+}
+
+fn check_profanity(profanity: &Profanity) -> Check {
+    let start = Instant::now();
+
+    match profanity.unavailable_for() {
+        None => check(Status::Up, start, None),
+        Some(secs) => check(
+            Status::Degraded,
+            start,
+            Some(format!("Failing, called again in {}s", secs)),
+        ),
+    }
+}
+
+fn check(status: Status, start: Instant, error: Option<String>) -> Check {
+    Check {
+        status,
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        error,
+    }
+}
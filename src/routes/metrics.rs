@@ -0,0 +1,20 @@
+use crate::metrics;
+use crate::store::Store;
+
+/// Prometheus metrics of this instance
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format",
+            body = String, content_type = "text/plain; version=0.0.4"),
+    )
+)]
+pub async fn get_metrics(store: Store) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::with_header(
+        metrics::encode(&store),
+        "content-type",
+        metrics::CONTENT_TYPE,
+    ))
+}
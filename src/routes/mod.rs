@@ -1,2 +1,188 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use handle_errors::{return_error, Error};
+use warp::http::HeaderValue;
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+use crate::cli::ServeArgs;
+use crate::compression;
+use crate::cors::{self, Cors};
+use crate::idempotency::Idempotency;
+use crate::profanity::Profanity;
+use crate::rate_limit::RateLimiter;
+use crate::shutdown::Readiness;
+use crate::store::Store;
+
+pub mod admin;
 pub mod answer;
+pub mod docs;
+pub mod events;
+pub mod health;
+pub mod metrics;
 pub mod question;
+pub mod redirect;
+pub mod v1;
+
+/// When the unversioned paths were deprecated in favour of `/v1`
+pub const LEGACY_DEPRECATED_AT: i64 = 1_792_281_600; // 2026-10-18T00:00:00Z
+
+/// All routes of the API with CORS, tracing, error handling, response
+/// compression and request metrics applied, as served by `minimal-warp serve`.
+/// Clients are only rate limited when requests carry a `ClientAddr`.
+///
+/// Each version of the API is mounted under its own prefix with its own
+/// handlers and types, so a `/v2` can be added next to `v1::routes`
+/// without touching it. The unversioned paths are aliases of `/v1`.
+pub fn routes(
+    store: Store,
+    profanity: Profanity,
+    readiness: Readiness,
+    args: &ServeArgs,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let limiter = RateLimiter::new(
+        args.rate_limits.clone(),
+        args.trusted_proxies.clone(),
+        args.rate_limit_shared.then(|| store.clone()),
+    );
+    let idempotency = Idempotency::new(
+        store.clone(),
+        Duration::from_secs(args.idempotency_ttl),
+        args.trusted_proxies.clone(),
+    );
+    let v1 = v1::routes(
+        store.clone(),
+        profanity.clone(),
+        limiter,
+        idempotency,
+        readiness.clone(),
+        args,
+    );
+
+    let store_filter = warp::any().map(move || store.clone());
+    let profanity_filter = warp::any().map(move || profanity.clone());
+    let readiness_filter = warp::any().map(move || readiness.clone());
+
+    let cors = Cors::new(args).unwrap_or_else(|e| panic!("Invalid CORS configuration: {}", e));
+
+    let sunset = args.legacy_sunset;
+    let legacy = warp::path::full()
+        .and(v1.clone())
+        .map(move |path: FullPath, reply| deprecated(reply, &path, sunset));
+    let v1 = warp::path("v1").and(v1);
+
+    let live = warp::get()
+        .and(warp::path("health"))
+        .and(warp::path("live"))
+        .and(warp::path::end())
+        .and_then(health::liveness);
+
+    let ready = warp::get()
+        .and(warp::path("health"))
+        .and(warp::path("ready"))
+        .and(warp::path::end())
+        .and(readiness_filter)
+        .and(store_filter.clone())
+        .and(profanity_filter)
+        .and_then(health::readiness);
+
+    let get_metrics = warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(store_filter)
+        .and_then(metrics::get_metrics);
+
+    let openapi_json = warp::get()
+        .and(warp::path("openapi.json"))
+        .and(warp::path::end())
+        .and_then(docs::openapi_json);
+
+    let swagger_config = Arc::new(utoipa_swagger_ui::Config::from(docs::SPEC_PATH));
+    let swagger_ui = warp::get()
+        .and(warp::path("docs"))
+        .and(warp::path::full())
+        .and(warp::path::tail())
+        .and(warp::any().map(move || swagger_config.clone()))
+        .and_then(docs::swagger_ui);
+
+    let api = v1
+        .or(legacy)
+        .or(live)
+        .or(ready)
+        .or(get_metrics)
+        .or(openapi_json)
+        .or(swagger_ui)
+        // Boxed, as the type of the whole filter tree would get too deep to compile otherwise
+        .map(Reply::into_response)
+        .boxed();
+    let api = cors::preflight(cors.clone())
+        .or(cors::check(cors.clone()).and(api))
+        .with(warp::trace::request())
+        .recover(recover);
+    // After `recover`, so that pages can read errors too
+    let api = cors::origin()
+        .and(api)
+        .map(move |origin, reply| cors.apply(origin, reply));
+
+    // After `recover` too, so the status is the one actually sent to the client
+    let api = warp::method()
+        .and(warp::path::full())
+        .and(warp::any().map(Instant::now))
+        .and(api)
+        .map(crate::metrics::record_request);
+
+    compression::accept_encoding()
+        .and(api)
+        .map(compression::compress)
+}
+
+/// `return_error`, marking the responses to requests refused by CORS as
+/// not answered by any route, whichever path they were sent to
+async fn recover(r: Rejection) -> Result<warp::reply::Response, Rejection> {
+    let unrouted = matches!(r.find(), Some(Error::CorsForbidden { .. }));
+    let mut res = return_error(r).await?.into_response();
+    if unrouted {
+        res.extensions_mut().insert(crate::metrics::Unrouted);
+    }
+    Ok(res)
+}
+
+/// Redirect every request to HTTPS on `https_port`, for the listener
+/// taking plain HTTP when the API is served over TLS. Only requests for
+/// one of `server_names` are redirected, unless there are none.
+pub fn https_redirect(
+    https_port: u16,
+    server_names: &[String],
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let server_names: Arc<[String]> = server_names.into();
+    warp::any()
+        .map(move || (https_port, server_names.clone()))
+        .untuple_one()
+        .and(warp::header::optional::<String>("host"))
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and_then(redirect::to_https)
+        .with(warp::trace::request())
+}
+
+/// Mark a response to an unversioned path as deprecated (RFC 9745),
+/// announcing when the path goes away (RFC 8594) and where it moved
+fn deprecated(reply: impl Reply, path: &FullPath, sunset: DateTime<Utc>) -> warp::reply::Response {
+    let mut res = reply.into_response();
+    let headers = res.headers_mut();
+
+    headers.insert(
+        "deprecation",
+        HeaderValue::from_str(&format!("@{}", LEGACY_DEPRECATED_AT)).unwrap(),
+    );
+    headers.insert(
+        "sunset",
+        HeaderValue::from_str(&sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).unwrap(),
+    );
+    // Paths of parsed URIs only consist of visible ASCII
+    let link = format!("</v1{}>; rel=\"successor-version\"", path.as_str());
+    headers.insert("link", HeaderValue::from_str(&link).unwrap());
+    res
+}
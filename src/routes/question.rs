@@ -1,18 +1,42 @@
 use std::collections::HashMap;
+use handle_errors::Error;
 use warp::hyper::StatusCode;
 use tracing::{instrument, Level};
 
+use crate::cli::ModerationMode;
+use crate::idempotency::{self, IdempotencyKey};
+use crate::metrics;
+use crate::negotiate::Format;
 use crate::store::Store;
-use crate::profanity::check_profanity;
+use crate::profanity::Profanity;
 
 use crate::types::{
+    idempotency::Recorded,
     pagination::{Pagination, extract_pagination},
-    question::{Question, NewQuestion},
+    question::{Question, QuestionId, NewQuestion},
 };
 
+/// List approved questions
+#[utoipa::path(
+    get,
+    path = "/questions",
+    tag = "questions",
+    params(Pagination),
+    responses(
+        (status = 200, description = "Approved questions ordered by id", content(
+            ([Question] = "application/json"),
+            ([Question] = "application/msgpack"),
+            ([Question] = "text/csv"),
+        )),
+        (status = 406, response = crate::openapi::NotAcceptable),
+        (status = 422, response = crate::openapi::InvalidRequest),
+        (status = 429, response = crate::openapi::TooManyRequests),
+    )
+)]
 #[instrument]
 pub async fn get_questions(
     params: HashMap<String, String>,
+    format: Format,
     store: Store,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     tracing::event!(target: "minimal_warp", tracing::Level::INFO, "querying questions");
@@ -27,66 +51,223 @@ pub async fn get_questions(
             Err(e) => return Err(warp::reject::custom(e)),
         };
 
-        Ok(warp::reply::json(&res))
+        Ok(format.reply(&res))
 }
 
+/// A single approved question
+#[utoipa::path(
+    get,
+    path = "/questions/{id}",
+    tag = "questions",
+    params(("id" = i32, Path, description = "Id of the question")),
+    responses(
+        (status = 200, description = "The question", content(
+            (Question = "application/json"),
+            (Question = "application/msgpack"),
+        )),
+        (status = 404, response = crate::openapi::NotFound),
+        (status = 406, response = crate::openapi::NotAcceptable),
+        (status = 429, response = crate::openapi::TooManyRequests),
+    )
+)]
+pub async fn get_question(
+    id: i32,
+    format: Format,
+    store: Store,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_question(id).await? {
+        Some(question) => Ok(format.reply(&question)),
+        None => Err(warp::reject::custom(Error::QuestionNotFound(id))),
+    }
+}
+
+/// Ask a question. Offensive words are handled according to the
+/// policies of `add_question`. Retries sent with the same
+/// `Idempotency-Key` get the original response.
+#[utoipa::path(
+    post,
+    path = "/questions",
+    tag = "questions",
+    params(("Idempotency-Key" = Option<String>, Header,
+        description = "Replay the response to an earlier request with this key instead of adding the question again")),
+    request_body(content(
+        (NewQuestion = "application/json"),
+        (NewQuestion = "application/x-www-form-urlencoded"),
+        (NewQuestion = "application/msgpack"),
+    )),
+    responses(
+        (status = 201, description = "The stored question",
+            headers(("location" = String, description = "Path of the question")),
+            content((Question = "application/json"), (Question = "application/msgpack"))),
+        (status = 202, description = "The submitted question, queued for moderation",
+            headers(("location" = String, description = "Path of the question")),
+            content((Question = "application/json"), (Question = "application/msgpack"))),
+        (status = 400, response = crate::openapi::InvalidIdempotencyKey),
+        (status = 406, response = crate::openapi::NotAcceptable),
+        (status = 409, response = crate::openapi::IdempotencyKeyInUse),
+        (status = 413, response = crate::openapi::PayloadTooLarge),
+        (status = 415, response = crate::openapi::UnsupportedMediaType),
+        (status = 422, response = crate::openapi::InvalidContent),
+        (status = 429, response = crate::openapi::TooManyRequests),
+        (status = 500, response = crate::openapi::UpstreamError),
+        (status = 503, response = crate::openapi::ServiceUnavailable),
+    )
+)]
 pub async fn add_question(
     store: Store,
+    profanity: Profanity,
+    moderation: ModerationMode,
+    key: Option<IdempotencyKey>,
+    format: Format,
     new_question: NewQuestion,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let title = match check_profanity(new_question.title).await {
-        Ok(res) => res,
-        Err(e) => return Err(warp::reject::custom(e)),
-    };
+    let fingerprint = idempotency::fingerprint(format, &new_question);
+    // Boxed, as the filter chain's future would get too big for the stack otherwise
+    idempotency::once(
+        key,
+        fingerprint,
+        Box::pin(create_question(store, profanity, moderation, format, new_question)),
+    )
+    .await
+}
 
-    let content = match check_profanity(new_question.content).await {
-        Ok(res) => res,
-        Err(e) => return Err(warp::reject::custom(e)),
-    };
+async fn create_question(
+    store: Store,
+    profanity: Profanity,
+    moderation: ModerationMode,
+    format: Format,
+    new_question: NewQuestion,
+) -> Result<Recorded, warp::Rejection> {
+    if moderation == ModerationMode::Async {
+        let question = store.queue_question(new_question).await?;
+        metrics::QUESTIONS_CREATED.inc();
+        return Ok(Recorded::accepted(
+            format.media_type(),
+            location(&question.id),
+            format.encode(&question),
+        ));
+    }
+
+    let review = profanity
+        .review(
+            "add_question",
+            vec![("title", new_question.title), ("content", new_question.content)],
+        )
+        .await?;
+    if let Some(e) = review.rejection() {
+        store.audit_profanity(&review).await?;
+        return Err(warp::reject::custom(e));
+    }
 
     let question = NewQuestion {
-        title,
-        content,
+        title: review.checked("title").content,
+        content: review.checked("content").content,
         tags: new_question.tags,
     };
 
-    match store.add_question(question).await {
-        Ok(_) => Ok(warp::reply::with_status("Question added", StatusCode::OK)),
+    match store.clone().add_question(question, review.needs_review(), Some(&review)).await {
+        Ok(question) => {
+            metrics::QUESTIONS_CREATED.inc();
+            Ok(Recorded::created(
+                format.media_type(),
+                location(&question.id),
+                format.encode(&question),
+            ))
+        }
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
 
+/// Path of a question in the current version of the API
+fn location(id: &QuestionId) -> String {
+    format!("/v1/questions/{}", id.0)
+}
+
+/// Replace a question's title, content and tags. Without a moderation
+/// queue the new text is checked right away, so a question rejected
+/// earlier is shown again once edited clean.
+#[utoipa::path(
+    put,
+    path = "/questions/{id}",
+    tag = "questions",
+    params(("id" = i32, Path, description = "Id of the question")),
+    request_body(content(
+        (Question = "application/json"),
+        (Question = "application/x-www-form-urlencoded"),
+        (Question = "application/msgpack"),
+    )),
+    responses(
+        (status = 200, description = "The stored question", content(
+            (Question = "application/json"),
+            (Question = "application/msgpack"),
+        )),
+        (status = 202, description = "The submitted question, queued for moderation", content(
+            (Question = "application/json"),
+            (Question = "application/msgpack"),
+        )),
+        (status = 406, response = crate::openapi::NotAcceptable),
+        (status = 413, response = crate::openapi::PayloadTooLarge),
+        (status = 415, response = crate::openapi::UnsupportedMediaType),
+        (status = 422, response = crate::openapi::InvalidContent),
+        (status = 429, response = crate::openapi::TooManyRequests),
+        (status = 500, response = crate::openapi::UpstreamError),
+        (status = 503, response = crate::openapi::ServiceUnavailable),
+    )
+)]
 pub async fn update_question(
     id: i32,
     store: Store,
+    profanity: Profanity,
+    moderation: ModerationMode,
+    format: Format,
     question: Question,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let title = check_profanity(question.title);
-    let content = check_profanity(question.content);
-
-    let (title, content) = tokio::join!(title, content);
-
-    if title.is_err() {
-        return Err(warp::reject::custom(title.unwrap_err()));
+    if moderation == ModerationMode::Async {
+        let res = store.queue_question_update(question, id).await?;
+        return Ok(warp::reply::with_status(
+            format.reply(&res),
+            StatusCode::ACCEPTED,
+        ));
     }
 
-    if content.is_err() {
-        return Err(warp::reject::custom(content.unwrap_err()));
+    let review = profanity
+        .review(
+            "update_question",
+            vec![("title", question.title), ("content", question.content)],
+        )
+        .await?;
+    if let Some(e) = review.rejection() {
+        store.audit_profanity(&review).await?;
+        return Err(warp::reject::custom(e));
     }
 
     let question = Question {
         id: question.id,
-        title: title.unwrap(),
-        content: content.unwrap(),
+        title: review.checked("title").content,
+        content: review.checked("content").content,
         tags: question.tags,
     };
 
-    match store.update_question(question, id).await {
-        Ok(res) => Ok(warp::reply::json(&res)),
-        Err(e) => return Err(warp::reject::custom(e)),
+    match store.clone().update_question(question, id, review.needs_review(), Some(&review)).await {
+        Ok(res) => {
+            Ok(warp::reply::with_status(format.reply(&res), StatusCode::OK))
+        }
+        Err(e) => Err(warp::reject::custom(e)),
     }
 }
 
+/// Delete a question together with its answers
+#[utoipa::path(
+    delete,
+    path = "/questions/{id}",
+    tag = "questions",
+    params(("id" = i32, Path, description = "Id of the question")),
+    responses(
+        (status = 200, description = "Question deleted", body = String, example = "Question 1 deleted"),
+        (status = 422, response = crate::openapi::InvalidRequest),
+        (status = 429, response = crate::openapi::TooManyRequests),
+    )
+)]
 pub async fn delete_question(
     id: i32,
     store: Store,
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use warp::http::{header, Response, StatusCode};
+use warp::hyper::Body;
+use warp::path::FullPath;
+
+/// Send the client to the same path and query over HTTPS, on `https_port`,
+/// if it asked for one of `server_names`, or any host when there are none.
+/// 308 keeps the method and body of the request, unlike 301.
+pub async fn to_https(
+    https_port: u16,
+    server_names: Arc<[String]>,
+    host: Option<String>,
+    path: FullPath,
+    query: String,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let host = match host.as_deref().and_then(hostname) {
+        Some(host) => host,
+        None => return Ok(bad_request("Missing or invalid Host header")),
+    };
+    if !server_names.is_empty() && !server_names.iter().any(|name| same_host(name, host)) {
+        return Ok(bad_request("Unknown host"));
+    }
+
+    let mut location = format!("https://{}", host);
+    if https_port != 443 {
+        location.push_str(&format!(":{}", https_port));
+    }
+    location.push_str(path.as_str());
+    if !query.is_empty() {
+        location.push('?');
+        location.push_str(&query);
+    }
+
+    // Only visible ASCII passes `hostname`, and so do parsed paths and queries
+    Ok(Response::builder()
+        .status(StatusCode::PERMANENT_REDIRECT)
+        .header(header::LOCATION, location)
+        .body(Body::empty())
+        .unwrap())
+}
+
+fn bad_request(message: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(message))
+        .unwrap()
+}
+
+/// Whether the configured server name `name` is `host`. Names are case
+/// insensitive, may end in a dot, and IPv6 addresses may lack brackets.
+fn same_host(name: &str, host: &str) -> bool {
+    let normalize = |host: &str| {
+        host.trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_end_matches('.')
+            .to_ascii_lowercase()
+    };
+    normalize(name) == normalize(host)
+}
+
+/// The host of a `Host` header without its port, if it only consists of
+/// characters of domain names and IP addresses, so it can't add a path or
+/// credentials to the redirect. Whether it is ours is up to the caller.
+fn hostname(host: &str) -> Option<&str> {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => &host[..rest.find(']')? + 2],
+        None => host.split(':').next()?,
+    };
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '[' | ']' | ':'));
+    valid.then_some(name)
+}
@@ -0,0 +1,212 @@
+use utoipa::OpenApi;
+use warp::Filter;
+
+use crate::auth::AdminAuth;
+use crate::body::BodyLimits;
+use crate::cli::ServeArgs;
+use crate::idempotency::Idempotency;
+use crate::negotiate::{self, LIST, SINGLE};
+use crate::openapi;
+use crate::profanity::Profanity;
+use crate::rate_limit::{self, RateLimiter};
+use crate::shutdown::Readiness;
+use crate::store::Store;
+
+use super::{admin, answer, events, question};
+
+/// Version 1 of the API, relative to its `/v1` prefix
+pub fn routes(
+    store: Store,
+    profanity: Profanity,
+    limiter: RateLimiter,
+    idempotency: Idempotency,
+    readiness: Readiness,
+    args: &ServeArgs,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let limits = BodyLimits::new(args.body_limits.clone());
+    let admin = AdminAuth::new(args.admin_token.as_deref());
+    let moderation = args.moderation;
+    let events = store.events.clone();
+    let store_filter = warp::any().map(move || store.clone());
+    let events_filter = warp::any().map(move || events.clone());
+    let profanity_filter = warp::any().map(move || profanity.clone());
+    let moderation_filter = warp::any().map(move || moderation);
+    let readiness_filter = warp::any().map(move || readiness.clone());
+
+    let get_questions = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path::end())
+        .and(limiter.check("get_questions"))
+        .and(
+            warp::query()
+                .and(negotiate::accept(LIST))
+                .and(store_filter.clone())
+                .and_then(question::get_questions),
+        )
+        .map(rate_limit::with_headers);
+
+    let get_question = warp::get()
+        .and(warp::path("questions"))
+        .and(limiter.check("get_question"))
+        .and(
+            warp::path::param::<i32>()
+                .and(warp::path::end())
+                .and(negotiate::accept(SINGLE))
+                .and(store_filter.clone())
+                .and_then(question::get_question),
+        )
+        .map(rate_limit::with_headers);
+
+    let add_question = warp::post()
+        .and(warp::path("questions"))
+        .and(warp::path::end())
+        .and(limiter.check("add_question"))
+        .and(
+            store_filter
+                .clone()
+                .and(profanity_filter.clone())
+                .and(moderation_filter)
+                .and(idempotency.key("add_question"))
+                .and(negotiate::accept(SINGLE))
+                .and(negotiate::body(limits.bytes("add_question")))
+                .and_then(question::add_question),
+        )
+        .map(rate_limit::with_headers);
+
+    let update_question = warp::put()
+        .and(warp::path("questions"))
+        .and(limiter.check("update_question"))
+        .and(
+            warp::path::param::<i32>()
+                .and(warp::path::end())
+                .and(store_filter.clone())
+                .and(profanity_filter.clone())
+                .and(moderation_filter)
+                .and(negotiate::accept(SINGLE))
+                .and(negotiate::body(limits.bytes("update_question")))
+                .and_then(question::update_question),
+        )
+        .map(rate_limit::with_headers);
+
+    let delete_question = warp::delete()
+        .and(warp::path("questions"))
+        .and(limiter.check("delete_question"))
+        .and(
+            warp::path::param::<i32>()
+                .and(warp::path::end())
+                .and(store_filter.clone())
+                .and_then(question::delete_question),
+        )
+        .map(rate_limit::with_headers);
+
+    let get_answer = warp::get()
+        .and(warp::path("answers"))
+        .and(limiter.check("get_answer"))
+        .and(
+            warp::path::param::<i32>()
+                .and(warp::path::end())
+                .and(negotiate::accept(SINGLE))
+                .and(store_filter.clone())
+                .and_then(answer::get_answer),
+        )
+        .map(rate_limit::with_headers);
+
+    let add_answer = warp::post()
+        .and(warp::path("answers"))
+        .and(warp::path::end())
+        .and(limiter.check("add_answer"))
+        .and(
+            store_filter
+                .clone()
+                .and(profanity_filter)
+                .and(moderation_filter)
+                .and(idempotency.key("add_answer"))
+                .and(negotiate::accept(SINGLE))
+                .and(negotiate::body(limits.bytes("add_answer")))
+                .and_then(answer::add_answer),
+        )
+        .map(rate_limit::with_headers);
+
+    let export = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(admin.require())
+        .and(store_filter.clone())
+        .and_then(admin::export);
+
+    let import = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("import"))
+        .and(warp::path::end())
+        .and(admin.require())
+        .and(warp::query())
+        .and(store_filter)
+        .and(limits.stream("import"))
+        .and_then(admin::import);
+
+    let subscribe = warp::get()
+        .and(warp::path("ws"))
+        .and(warp::path::end())
+        .and(limiter.check("subscribe"))
+        .and(
+            warp::ws()
+                .map(Some)
+                .or(warp::any().map(|| None))
+                .unify()
+                .and(events_filter)
+                .and(readiness_filter)
+                .and_then(events::subscribe),
+        )
+        .map(rate_limit::with_headers);
+
+    get_questions
+        .or(get_question)
+        .or(add_question)
+        .or(update_question)
+        .or(delete_question)
+        .or(get_answer)
+        .or(add_answer)
+        .or(export)
+        .or(import)
+        .or(subscribe)
+}
+
+/// The operations of `routes`, relative to the `/v1` prefix
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        question::get_questions,
+        question::get_question,
+        question::add_question,
+        question::update_question,
+        question::delete_question,
+        answer::get_answer,
+        answer::add_answer,
+        admin::export,
+        admin::import,
+        events::subscribe,
+    ),
+    components(
+        schemas(
+            crate::types::event::ClientMessage,
+            crate::types::event::ServerMessage,
+            crate::types::event::Topic
+        ),
+        responses(
+            openapi::InvalidRequest,
+            openapi::NotFound,
+            openapi::Unauthorized,
+            openapi::InvalidContent,
+            openapi::UnsupportedMediaType,
+            openapi::PayloadTooLarge,
+            openapi::NotAcceptable,
+            openapi::InvalidIdempotencyKey,
+            openapi::IdempotencyKeyInUse,
+            openapi::TooManyRequests,
+            openapi::ServiceUnavailable,
+            openapi::UpstreamError
+        )
+    )
+)]
+pub struct ApiDoc;
@@ -0,0 +1,225 @@
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::profanity::{Checked, Profanity};
+use crate::types::policy::Policy;
+use crate::store::Store;
+use crate::types::{
+    answer::{Answer, AnswerId, NewAnswer},
+    bulk::QuestionRecord,
+    question::{NewQuestion, Question, QuestionId},
+};
+
+/// Content of a fixture file
+#[derive(Deserialize, Debug, Default)]
+pub struct Fixture {
+    #[serde(default)]
+    pub questions: Vec<QuestionRecord>,
+}
+
+/// Number of records written by a seeding run
+#[derive(Debug, Default)]
+pub struct SeedReport {
+    pub questions: usize,
+    pub answers: usize,
+}
+
+#[derive(Debug)]
+pub enum SeedError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Yaml(serde_yaml::Error),
+    Store(handle_errors::Error),
+}
+
+impl std::fmt::Display for SeedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeedError::Io(err) => write!(f, "Cannot read fixture: {}", err),
+            SeedError::Json(err) => write!(f, "Invalid JSON fixture: {}", err),
+            SeedError::Yaml(err) => write!(f, "Invalid YAML fixture: {}", err),
+            SeedError::Store(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<handle_errors::Error> for SeedError {
+    fn from(err: handle_errors::Error) -> Self {
+        SeedError::Store(err)
+    }
+}
+
+/// Read a fixture file, picking the format from its extension
+pub fn read_fixture(path: &Path) -> Result<Fixture, SeedError> {
+    let raw = std::fs::read_to_string(path).map_err(SeedError::Io)?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&raw).map_err(SeedError::Yaml),
+        _ => serde_json::from_str(&raw).map_err(SeedError::Json),
+    }
+}
+
+/// Write all questions and answers of `fixture` through the store.
+/// With `profanity` given, titles and contents are censored first.
+/// Route policies don't apply here; fixtures are always censored.
+pub async fn load_fixture(
+    store: &Store,
+    fixture: Fixture,
+    profanity: Option<&Profanity>,
+) -> Result<SeedReport, SeedError> {
+    let mut report = SeedReport::default();
+    // Whether records were written with explicit ids since the id
+    // sequences were last moved past them
+    let mut unsynced = false;
+
+    for question in fixture.questions {
+        let (title, content) = if let Some(profanity) = profanity {
+            let (title, content) = tokio::join!(
+                profanity.detect(question.title),
+                profanity.detect(question.content)
+            );
+            (title?.apply(Policy::Censor), content?.apply(Policy::Censor))
+        } else {
+            (Checked::skipped(question.title), Checked::skipped(question.content))
+        };
+        let needs_review = title.needs_review || content.needs_review;
+
+        let stored = match question.id {
+            Some(id) => {
+                unsynced = true;
+                store
+                    .upsert_question(
+                        Question {
+                            id: QuestionId(id),
+                            title: title.content,
+                            content: content.content,
+                            tags: question.tags,
+                        },
+                        needs_review,
+                    )
+                    .await?
+            }
+            None => {
+                sync_before_insert(store, &mut unsynced).await?;
+                store
+                    .clone()
+                    .add_question(
+                        NewQuestion {
+                            title: title.content,
+                            content: content.content,
+                            tags: question.tags,
+                        },
+                        needs_review,
+                        None,
+                    )
+                    .await?
+            }
+        };
+        report.questions += 1;
+
+        for answer in question.answers {
+            let content = if let Some(profanity) = profanity {
+                profanity.detect(answer.content).await?.apply(Policy::Censor)
+            } else {
+                Checked::skipped(answer.content)
+            };
+
+            match answer.id {
+                Some(id) => {
+                    unsynced = true;
+                    store
+                        .upsert_answer(
+                            Answer {
+                                id: AnswerId(id),
+                                content: content.content,
+                                question_id: stored.id.clone(),
+                            },
+                            content.needs_review,
+                        )
+                        .await?;
+                }
+                None => {
+                    sync_before_insert(store, &mut unsynced).await?;
+                    store
+                        .add_answer(
+                            NewAnswer {
+                                content: content.content,
+                                question_id: stored.id.clone(),
+                            },
+                            content.needs_review,
+                            None,
+                        )
+                        .await?;
+                }
+            }
+            report.answers += 1;
+        }
+    }
+
+    if unsynced {
+        store.sync_id_sequences().await?;
+    }
+    Ok(report)
+}
+
+/// Move the id sequences past records upserted so far, so an insert
+/// without an id doesn't collide with them
+async fn sync_before_insert(store: &Store, unsynced: &mut bool) -> Result<(), SeedError> {
+    if *unsynced {
+        store.sync_id_sequences().await?;
+        *unsynced = false;
+    }
+    Ok(())
+}
+
+const WORDS: &[&str] = &[
+    "async", "await", "borrow", "cargo", "closure", "crate", "deadlock", "enum",
+    "filter", "future", "generic", "handler", "iterator", "lifetime", "macro",
+    "migration", "module", "mutex", "option", "pool", "query", "reference",
+    "result", "route", "runtime", "schema", "server", "slice", "stream", "string",
+    "struct", "task", "thread", "trait", "vector", "warp",
+];
+
+const TAGS: &[&str] = &["general", "rust", "warp", "tokio", "sqlx", "postgres"];
+
+/// Insert `count` questions made of random words, e.g. for load testing.
+/// The generated text is built from a fixed word list, so it never goes
+/// through the profanity API.
+pub async fn generate_questions(store: &Store, count: u32) -> Result<SeedReport, SeedError> {
+    let mut report = SeedReport::default();
+
+    for _ in 0..count {
+        let question = random_question(&mut rand::thread_rng());
+        store.clone().add_question(question, false, None).await?;
+        report.questions += 1;
+    }
+
+    Ok(report)
+}
+
+fn random_question<R: Rng>(rng: &mut R) -> NewQuestion {
+    let mut sentence = |min: usize, max: usize| {
+        let len = rng.gen_range(min..=max);
+        (0..len)
+            .map(|_| *WORDS.choose(rng).unwrap())
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let title = format!("How does {} work?", sentence(2, 6));
+    let content = format!("{}.", sentence(15, 60));
+    let tag_count = rng.gen_range(0..=3);
+    let tags = TAGS
+        .choose_multiple(rng, tag_count)
+        .map(|tag| tag.to_string())
+        .collect::<Vec<_>>();
+
+    NewQuestion {
+        title,
+        content,
+        tags: if tags.is_empty() { None } else { Some(tags) },
+    }
+}
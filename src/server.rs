@@ -0,0 +1,192 @@
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use socket2::{Domain, Socket, Type};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+use warp::hyper::server::accept::{self, Accept};
+use warp::hyper::service::{make_service_fn, service_fn};
+use warp::{Filter, Reply};
+
+use crate::rate_limit::ClientAddr;
+use crate::request_id;
+
+/// How long a client may take to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connections accepted but not yet picked up by the server
+const BACKLOG: usize = 1024;
+
+/// A connection accepted on one of the listeners, in plain text or TLS
+pub struct Connection {
+    io: Io,
+    remote_addr: SocketAddr,
+}
+
+enum Io {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection {
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+}
+
+/// Listen on `addr`. IPv6 sockets only take IPv6 connections, so that
+/// `0.0.0.0` and `[::]` can be listened on side by side with the same port.
+pub fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG as i32)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Accept connections on all `listeners` until `stop` is cancelled, doing
+/// the TLS handshake first if `tls` is given. Handshakes run concurrently,
+/// so a slow client doesn't hold up the others.
+pub fn incoming(
+    listeners: Vec<TcpListener>,
+    tls: Option<TlsAcceptor>,
+    stop: CancellationToken,
+) -> impl Accept<Conn = Connection, Error = io::Error> {
+    let (tx, rx) = mpsc::channel(BACKLOG);
+
+    for listener in listeners {
+        let (tx, tls, stop) = (tx.clone(), tls.clone(), stop.clone());
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote_addr) = tokio::select! {
+                    _ = stop.cancelled() => return,
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            // Mostly running out of file descriptors, which
+                            // would fail again right away
+                            tracing::event!(tracing::Level::ERROR, "Cannot accept connection: {}", e);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            continue;
+                        }
+                    },
+                };
+                let _ = stream.set_nodelay(true);
+
+                let tls = match &tls {
+                    Some(tls) => tls.clone(),
+                    None => {
+                        let _ = tx.send(Connection { io: Io::Plain(stream), remote_addr }).await;
+                        continue;
+                    }
+                };
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let io = Io::Tls(Box::new(stream));
+                            let _ = tx.send(Connection { io, remote_addr }).await;
+                        }
+                        Ok(Err(e)) => tracing::event!(
+                            tracing::Level::DEBUG,
+                            "TLS handshake with {} failed: {}",
+                            remote_addr,
+                            e
+                        ),
+                        Err(_) => tracing::event!(
+                            tracing::Level::DEBUG,
+                            "TLS handshake with {} timed out",
+                            remote_addr
+                        ),
+                    }
+                });
+            }
+        });
+    }
+
+    accept::from_stream(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|conn| (Ok(conn), rx))
+    }))
+}
+
+/// Serve `routes` on the `incoming` connections until `stop` is cancelled,
+/// then wait for in-flight requests to finish. Each request is tagged with
+/// its `ClientAddr` and a request id.
+pub async fn serve<F>(
+    incoming: impl Accept<Conn = Connection, Error = io::Error>,
+    routes: F,
+    stop: CancellationToken,
+) -> Result<(), warp::hyper::Error>
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let service = warp::service(routes);
+    let make_service = make_service_fn(move |conn: &Connection| {
+        let service = service.clone();
+        let addr = ClientAddr(conn.remote_addr());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut req| {
+                req.extensions_mut().insert(addr);
+                request_id::handle(service.clone(), req)
+            }))
+        }
+    });
+
+    warp::hyper::Server::builder(incoming)
+        .serve(make_service)
+        .with_graceful_shutdown(async move { stop.cancelled().await })
+        .await
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            Io::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Io::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().io {
+            Io::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Io::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            Io::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Io::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().io {
+            Io::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Io::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::{Arc, OnceLock};
+
+use tokio_util::sync::CancellationToken;
+
+/// Whether the server still accepts work. Flips to not ready as soon as
+/// shutdown starts, so load balancers stop routing new requests to us
+/// while in-flight ones are drained.
+#[derive(Clone, Debug, Default)]
+pub struct Readiness {
+    shutting_down: CancellationToken,
+    /// Number of pending migrations, or why they couldn't be counted.
+    /// Checked once at startup, since the embedded migrations can only
+    /// change with a new binary.
+    migrations: Arc<OnceLock<Result<usize, String>>>,
+}
+
+impl Readiness {
+    pub fn migrations_checked(&self, pending: Result<usize, String>) {
+        let _ = self.migrations.set(pending);
+    }
+
+    /// The outcome of the startup migration check, if it was made
+    pub fn pending_migrations(&self) -> Option<&Result<usize, String>> {
+        self.migrations.get()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        !self.shutting_down.is_cancelled()
+    }
+
+    pub fn shutting_down(&self) {
+        self.shutting_down.cancel();
+    }
+
+    /// Completes once shutdown started, for connections which outlive
+    /// requests to close themselves
+    pub async fn stopping(&self) {
+        self.shutting_down.cancelled().await
+    }
+}
+
+/// Completes on the first SIGINT (Ctrl+C) or SIGTERM
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Cannot listen for SIGINT");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Cannot listen for SIGTERM")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
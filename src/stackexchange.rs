@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::profanity::{Checked, Profanity};
+use crate::types::policy::Policy;
+use crate::store::Store;
+use crate::types::{
+    answer::NewAnswer,
+    question::{NewQuestion, QuestionId},
+};
+
+/// Format of the `CreationDate` attribute, e.g. `2008-07-31T21:42:52.667`
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// What was imported from a dump, and what had to be left out
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub questions: usize,
+    pub answers: usize,
+    pub accepted_answers: usize,
+    pub skipped_posts: usize,
+    /// Posts an earlier run already imported
+    pub already_imported: usize,
+    pub skipped_tags: usize,
+    /// Users whose display names are stored as the author of their posts
+    pub users: usize,
+    /// Users without an id or a display name
+    pub skipped_users: usize,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    Xml(quick_xml::Error),
+    Store(handle_errors::Error),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(err) => write!(f, "Cannot read dump: {}", err),
+            ImportError::Xml(err) => write!(f, "Invalid XML in dump: {}", err),
+            ImportError::Store(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<handle_errors::Error> for ImportError {
+    fn from(err: handle_errors::Error) -> Self {
+        ImportError::Store(err)
+    }
+}
+
+/// Reads the `<row .../>` elements of a dump file one at a time,
+/// so files larger than memory can be imported
+struct RowReader {
+    reader: Reader<BufReader<File>>,
+    buf: Vec<u8>,
+}
+
+impl RowReader {
+    /// Returns `None` if the file doesn't exist, since only `Posts.xml` is required
+    fn open(path: &Path) -> Result<Option<Self>, ImportError> {
+        match File::open(path) {
+            Ok(file) => Ok(Some(RowReader {
+                reader: Reader::from_reader(BufReader::new(file)),
+                buf: Vec::new(),
+            })),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(ImportError::Io(e)),
+        }
+    }
+
+    /// The attributes of the next row, keyed by name
+    fn next_row(&mut self) -> Result<Option<HashMap<String, String>>, ImportError> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf) {
+                Ok(Event::Empty(e)) | Ok(Event::Start(e)) if e.name().as_ref() == b"row" => {
+                    let mut row = HashMap::new();
+                    for attr in e.attributes() {
+                        let attr = attr.map_err(|e| ImportError::Xml(e.into()))?;
+                        let value = attr.unescape_value().map_err(ImportError::Xml)?;
+                        row.insert(
+                            String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                            value.into_owned(),
+                        );
+                    }
+                    return Ok(Some(row));
+                }
+                Ok(Event::Eof) => return Ok(None),
+                Ok(_) => continue,
+                Err(e) => return Err(ImportError::Xml(e)),
+            }
+        }
+    }
+}
+
+/// Import questions and answers from an extracted StackExchange dump.
+/// `Posts.xml` is required; if `Tags.xml` exists, tags not listed there are
+/// dropped from questions. Original creation times and accepted answers are kept.
+/// There are no accounts to map users onto, so the display names from
+/// `Users.xml` are kept as the author of each post instead.
+/// Posts remember their id in the dump, so a rerun after an interrupted
+/// import picks up where it stopped instead of importing posts twice.
+pub async fn import_dump(
+    store: &Store,
+    dir: &Path,
+    profanity: Option<&Profanity>,
+) -> Result<ImportReport, ImportError> {
+    let mut report = ImportReport::default();
+
+    let known_tags = match RowReader::open(&dir.join("Tags.xml"))? {
+        Some(mut tags) => {
+            let mut known = HashSet::new();
+            while let Some(row) = tags.next_row()? {
+                if let Some(name) = row.get("TagName") {
+                    known.insert(name.clone());
+                }
+            }
+            Some(known)
+        }
+        None => None,
+    };
+
+    // Display names by dump user id
+    let mut users: HashMap<String, String> = HashMap::new();
+    if let Some(mut rows) = RowReader::open(&dir.join("Users.xml"))? {
+        while let Some(mut row) = rows.next_row()? {
+            match (row.remove("Id"), row.remove("DisplayName")) {
+                (Some(id), Some(name)) => {
+                    users.insert(id, name);
+                    report.users += 1;
+                }
+                _ => report.skipped_users += 1,
+            }
+        }
+    }
+
+    let mut posts = match RowReader::open(&dir.join("Posts.xml"))? {
+        Some(posts) => posts,
+        None => {
+            return Err(ImportError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Posts.xml not found",
+            )))
+        }
+    };
+
+    // Dump post ids of imported posts, and of accepted answers still to come
+    let mut questions = store.imported_questions().await?;
+    let answers = store.imported_answers().await?;
+    let mut accepted: HashMap<String, QuestionId> = HashMap::new();
+
+    while let Some(row) = posts.next_row()? {
+        let created_on = row
+            .get("CreationDate")
+            .and_then(|date| NaiveDateTime::parse_from_str(date, DATE_FORMAT).ok());
+        // Posts of deleted users only carry the name they had
+        let author = row
+            .get("OwnerUserId")
+            .and_then(|id| users.get(id))
+            .or_else(|| row.get("OwnerDisplayName"))
+            .cloned();
+
+        match (row.get("PostTypeId").map(String::as_str), row.get("Id"), created_on) {
+            (Some("1"), Some(id), Some(created_on)) => {
+                if let Some(question_id) = questions.get(id) {
+                    if let Some(answer_id) = row.get("AcceptedAnswerId") {
+                        accepted.insert(answer_id.clone(), question_id.clone());
+                    }
+                    report.already_imported += 1;
+                    continue;
+                }
+                let (title, content) = match (row.get("Title"), row.get("Body")) {
+                    (Some(title), Some(content)) => (title.clone(), content.clone()),
+                    _ => {
+                        report.skipped_posts += 1;
+                        continue;
+                    }
+                };
+                let (title, content) = if let Some(profanity) = profanity {
+                    let (title, content) =
+                        tokio::join!(profanity.detect(title), profanity.detect(content));
+                    (title?.apply(Policy::Censor), content?.apply(Policy::Censor))
+                } else {
+                    (Checked::skipped(title), Checked::skipped(content))
+                };
+
+                let mut tags = parse_tags(row.get("Tags").map(String::as_str).unwrap_or(""));
+                if let Some(known) = &known_tags {
+                    let before = tags.len();
+                    tags.retain(|tag| known.contains(tag));
+                    report.skipped_tags += before - tags.len();
+                }
+
+                let question = match store
+                    .add_question_created_on(
+                        NewQuestion {
+                            title: title.content,
+                            content: content.content,
+                            tags: if tags.is_empty() { None } else { Some(tags) },
+                        },
+                        created_on,
+                        author,
+                        id,
+                        title.needs_review || content.needs_review,
+                    )
+                    .await?
+                {
+                    Some(question) => question,
+                    // Imported by another run in the meantime
+                    None => {
+                        report.already_imported += 1;
+                        continue;
+                    }
+                };
+
+                if let Some(answer_id) = row.get("AcceptedAnswerId") {
+                    accepted.insert(answer_id.clone(), question.id.clone());
+                }
+                questions.insert(id.clone(), question.id);
+                report.questions += 1;
+            }
+            (Some("2"), Some(id), Some(created_on)) => {
+                let question_id = match row.get("ParentId").and_then(|parent| questions.get(parent)) {
+                    Some(question_id) => question_id.clone(),
+                    None => {
+                        report.skipped_posts += 1;
+                        continue;
+                    }
+                };
+                if let Some(answer_id) = answers.get(id) {
+                    if let Some(question_id) = accepted.remove(id) {
+                        store.set_accepted_answer(question_id, answer_id.clone()).await?;
+                    }
+                    report.already_imported += 1;
+                    continue;
+                }
+                let content = match row.get("Body") {
+                    Some(content) => match profanity {
+                        Some(profanity) => {
+                            profanity.detect(content.clone()).await?.apply(Policy::Censor)
+                        }
+                        None => Checked::skipped(content.clone()),
+                    },
+                    None => {
+                        report.skipped_posts += 1;
+                        continue;
+                    }
+                };
+
+                let answer = match store
+                    .add_answer_created_on(
+                        NewAnswer {
+                            content: content.content,
+                            question_id,
+                        },
+                        created_on,
+                        author,
+                        id,
+                        content.needs_review,
+                    )
+                    .await?
+                {
+                    Some(answer) => answer,
+                    None => {
+                        report.already_imported += 1;
+                        continue;
+                    }
+                };
+                report.answers += 1;
+
+                if let Some(question_id) = accepted.remove(id) {
+                    store.set_accepted_answer(question_id, answer.id).await?;
+                    report.accepted_answers += 1;
+                }
+            }
+            // Tag wikis, moderator nominations and other post types
+            _ => report.skipped_posts += 1,
+        }
+
+        let done = report.questions + report.answers + report.skipped_posts;
+        if done % 1000 == 0 {
+            tracing::event!(tracing::Level::INFO, "Processed {} posts", done);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Tags are stored either as `<rust><warp>` or, in newer dumps, as `|rust|warp|`
+fn parse_tags(raw: &str) -> Vec<String> {
+    raw.split(['<', '>', '|'])
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
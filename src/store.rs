@@ -1,10 +1,24 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
 use sqlx::postgres::{PgPoolOptions, PgPool, PgRow};
-use sqlx::Row;
+use sqlx::{Connection, Postgres, Row, Transaction};
+use tracing::instrument;
+use warp::http::StatusCode;
 
 use handle_errors::Error;
 
+use crate::events::{self, Events};
 use crate::types::answer::{NewAnswer, Answer, AnswerId};
+use crate::types::bulk::{AnswerRecord, ImportLineError, ImportReport, QuestionRecord};
+use crate::types::event::Event;
+use crate::types::idempotency::{Claim, Recorded};
 use crate::types::question::NewQuestion;
+use crate::types::rate_limit::{Limit, Quota, RateLimitRule};
+use crate::profanity::Review;
+use crate::types::moderation::{
+    ContentId, ModerationJob, ModerationOutcome, ModerationStatus, ModerationTarget,
+};
 use crate::types::{
     question::{Question, QuestionId},
 };
@@ -12,26 +26,25 @@ use crate::types::{
 #[derive(Clone, Debug)]
 pub struct Store {
     pub connection: PgPool,
+    pub events: Events,
 }
 
 impl Store {
-    pub async fn new(db_url: &str) -> Store {
-        let db_pool = match PgPoolOptions::new()
+    pub async fn connect(db_url: &str) -> Result<Store, sqlx::Error> {
+        let db_pool = PgPoolOptions::new()
             .max_connections(5)
-            .connect(db_url).await {
-                Ok(pool) => pool,
-                Err(_) => panic!("Couldn't establish DB connection!"),
-            };
+            .connect(db_url).await?;
 
-        Store { connection: db_pool, }
+        Ok(Store { connection: db_pool, events: Events::default() })
     }
 
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
     pub async fn get_questions(
         &self, 
         limit: Option<u32>, 
         offset: u32
     ) -> Result<Vec<Question>, Error> {
-        match sqlx::query("SELECT * from questions LIMIT $1 OFFSET $2")
+        match sqlx::query("SELECT * from questions WHERE status = 'approved' ORDER BY id LIMIT $1 OFFSET $2")
             .bind(limit)
             .bind(offset)
             .map(|row: PgRow| Question {
@@ -50,48 +63,271 @@ impl Store {
             }
     }
 
-    pub async fn add_question(
-        self,
-        new_question: NewQuestion
-    ) -> Result<Question, Error> {
-        tracing::event!(tracing::Level::INFO, "Attempting to add question");
-        match sqlx::query("INSERT INTO questions (title, content, tags) VALUES ($1, $2, $3) RETURNING id, title, content, tags")
-            .bind(new_question.title)
-            .bind(new_question.content)
-            .bind(new_question.tags)
+    /// A question, unless it doesn't exist or isn't approved
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn get_question(&self, question_id: i32) -> Result<Option<Question>, Error> {
+        match sqlx::query("SELECT id, title, content, tags FROM questions WHERE id = $1 AND status = 'approved'")
+            .bind(question_id)
             .map(|row: PgRow| Question {
                 id: QuestionId(row.get("id")),
                 title: row.get("title"),
                 content: row.get("content"),
-                tags: row.get("tags"),
+                tags: row.get("tags")
             })
-            .fetch_one(&self.connection)
+            .fetch_optional(&self.connection)
             .await {
                 Ok(question) => Ok(question),
                 Err(e) => {
                     tracing::event!(tracing::Level::ERROR, "{:?}", e);
                     Err(Error::DatabaseQueryError)
-                },
+                }
             }
     }
 
+    /// An answer, unless it doesn't exist or isn't approved
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn get_answer(&self, answer_id: i32) -> Result<Option<Answer>, Error> {
+        match sqlx::query("SELECT id, content, corresponding_question FROM answers WHERE id = $1 AND status = 'approved'")
+            .bind(answer_id)
+            .map(|row: PgRow| Answer {
+                id: AnswerId(row.get("id")),
+                content: row.get("content"),
+                question_id: QuestionId(row.get("corresponding_question")),
+            })
+            .fetch_optional(&self.connection)
+            .await {
+                Ok(answer) => Ok(answer),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                }
+            }
+    }
+
+    /// Let WebSocket subscribers know about `event`, on other instances
+    /// too when events are shared. The change stands if notifying fails.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    async fn publish(&self, event: Event) {
+        let payload = match self.events.send(event) {
+            Some(payload) => payload,
+            None => return,
+        };
+        if let Err(e) = sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(events::CHANNEL)
+            .bind(payload)
+            .execute(&self.connection)
+            .await
+        {
+            tracing::event!(tracing::Level::ERROR, "Cannot share event: {:?}", e);
+        }
+    }
+
+    /// Store a question, flagged for review if `needs_review`, together
+    /// with the findings of its profanity check in `audit`
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn add_question(
+        self,
+        new_question: NewQuestion,
+        needs_review: bool,
+        audit: Option<&Review>,
+    ) -> Result<Question, Error> {
+        tracing::event!(tracing::Level::INFO, "Attempting to add question");
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+            let question = sqlx::query("INSERT INTO questions (title, content, tags, needs_review) VALUES ($1, $2, $3, $4)
+            RETURNING id, title, content, tags")
+                .bind(new_question.title)
+                .bind(new_question.content)
+                .bind(new_question.tags)
+                .bind(needs_review)
+                .map(|row: PgRow| Question {
+                    id: QuestionId(row.get("id")),
+                    title: row.get("title"),
+                    content: row.get("content"),
+                    tags: row.get("tags"),
+                })
+                .fetch_one(&mut tx)
+                .await?;
+            if let Some(review) = audit {
+                insert_profanity_audit(&mut tx, review, Some(ContentId::Question(question.id.clone()))).await?;
+            }
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(question)
+        };
+
+        match result.await {
+            Ok(question) => {
+                self.publish(Event::QuestionCreated { question: question.clone() }).await;
+                Ok(question)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            },
+        }
+    }
+
+    /// Replace a question which passed the profanity check, approving it,
+    /// and keep the findings of the check in `audit`.
+    /// A question hidden until now, e.g. rejected by a moderation worker,
+    /// is published as created. Whether it needs review is decided by the
+    /// new text alone, so a clean edit clears an earlier flag.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
     pub async fn update_question(
         self,
         question: Question,
-        question_id: i32
+        question_id: i32,
+        needs_review: bool,
+        audit: Option<&Review>,
     ) -> Result<Question, Error> {
-        match sqlx::query("UPDATE questions SET title = $1, content = $2, tags = $3
-        WHERE id = $4
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+            let updated = sqlx::query("WITH previous AS (SELECT id, status FROM questions WHERE id = $6 FOR UPDATE)
+            UPDATE questions q SET title = $1, content = $2, tags = $3, needs_review = $4, status = $5
+            FROM previous WHERE q.id = previous.id
+            RETURNING q.id, q.title, q.content, q.tags, previous.status AS previous_status")
+                .bind(question.title)
+                .bind(question.content)
+                .bind(question.tags)
+                .bind(needs_review)
+                .bind(ModerationStatus::Approved.as_str())
+                .bind(question_id)
+                .map(|row: PgRow| {
+                    let question = Question {
+                        id: QuestionId(row.get("id")),
+                        title: row.get("title"),
+                        content: row.get("content"),
+                        tags: row.get("tags")
+                    };
+                    let previous_status: String = row.get("previous_status");
+                    (question, previous_status == ModerationStatus::Approved.as_str())
+                })
+                .fetch_optional(&mut tx)
+                .await?;
+            let updated = match updated {
+                Some(updated) => updated,
+                None => {
+                    tx.rollback().await?;
+                    return Err(sqlx::Error::RowNotFound);
+                }
+            };
+            if let Some(review) = audit {
+                insert_profanity_audit(&mut tx, review, Some(ContentId::Question(updated.0.id.clone()))).await?;
+            }
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(updated)
+        };
+
+        match result.await {
+            Ok((question, was_visible)) => {
+                let event = if was_visible {
+                    Event::QuestionUpdated { question: question.clone() }
+                } else {
+                    Event::QuestionCreated { question: question.clone() }
+                };
+                self.publish(event).await;
+                Ok(question)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            },
+        }
+    }
+
+    /// Delete a question. Subscribers only hear of it if they could see it.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn delete_question(self, question_id: i32) -> Result<bool, Error> {
+        match sqlx::query("DELETE FROM questions WHERE id = $1 RETURNING tags, status")
+            .bind(question_id)
+            .map(|row: PgRow| (row.get("tags"), row.get::<String, _>("status")))
+            .fetch_optional(&self.connection)
+            .await {
+                Ok(deleted) => {
+                    let visible = deleted.filter(|(_, status)| status == ModerationStatus::Approved.as_str());
+                    if let Some((tags, _)) = visible {
+                        let question_id = QuestionId(question_id);
+                        self.publish(Event::QuestionDeleted { question_id, tags }).await;
+                    }
+                    Ok(true)
+                }
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                },
+            }
+    }
+
+    /// Store an answer, flagged for review if `needs_review`, together
+    /// with the findings of its profanity check in `audit`. Subscribers
+    /// only hear of it if they can see the question it answers.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn add_answer(
+        &self,
+        new_answer: NewAnswer,
+        needs_review: bool,
+        audit: Option<&Review>,
+    ) -> Result<Answer, Error> {
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+            let added = sqlx::query("INSERT INTO answers (content, corresponding_question, needs_review) VALUES ($1, $2, $3)
+            RETURNING id, content, corresponding_question, status,
+                (SELECT tags FROM questions WHERE id = corresponding_question) AS tags,
+                (SELECT status FROM questions WHERE id = corresponding_question) AS question_status")
+                .bind(new_answer.content)
+                .bind(new_answer.question_id.0)
+                .bind(needs_review)
+                .map(|row: PgRow| {
+                    let answer = Answer {
+                        id: AnswerId(row.get("id")),
+                        content: row.get("content"),
+                        question_id: QuestionId(row.get("corresponding_question")),
+                    };
+                    let approved = ModerationStatus::Approved.as_str();
+                    let visible = row.get::<String, _>("status") == approved
+                        && row.get::<String, _>("question_status") == approved;
+                    (answer, row.get::<Option<Vec<String>>, _>("tags"), visible)
+                })
+                .fetch_one(&mut tx)
+                .await?;
+            if let Some(review) = audit {
+                insert_profanity_audit(&mut tx, review, Some(ContentId::Answer(added.0.id.clone()))).await?;
+            }
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(added)
+        };
+
+        match result.await {
+            Ok((answer, tags, visible)) => {
+                if visible {
+                    self.publish(Event::AnswerCreated { answer: answer.clone(), tags }).await;
+                }
+                Ok(answer)
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            },
+        }
+    }
+
+    /// Insert a question with a known id, or overwrite the existing one,
+    /// flagged for review if `needs_review`
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn upsert_question(&self, question: Question, needs_review: bool) -> Result<Question, Error> {
+        match sqlx::query("INSERT INTO questions (id, title, content, tags, needs_review) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (id) DO UPDATE SET title = $2, content = $3, tags = $4, needs_review = $5
         RETURNING id, title, content, tags")
+            .bind(question.id.0)
             .bind(question.title)
             .bind(question.content)
             .bind(question.tags)
-            .bind(question_id)
+            .bind(needs_review)
             .map(|row: PgRow| Question {
                 id: QuestionId(row.get("id")),
                 title: row.get("title"),
                 content: row.get("content"),
-                tags: row.get("tags")
+                tags: row.get("tags"),
             })
             .fetch_one(&self.connection)
             .await {
@@ -103,12 +339,64 @@ impl Store {
             }
     }
 
-    pub async fn delete_question(self, question_id: i32) -> Result<bool, Error> {
-        match sqlx::query("DELETE FROM questions WHERE id = $1")
-            .bind(question_id)
-            .execute(&self.connection)
+    /// Insert an answer with a known id, or overwrite the existing one,
+    /// flagged for review if `needs_review`
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn upsert_answer(&self, answer: Answer, needs_review: bool) -> Result<Answer, Error> {
+        match sqlx::query("INSERT INTO answers (id, content, corresponding_question, needs_review) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (id) DO UPDATE SET content = $2, corresponding_question = $3, needs_review = $4
+        RETURNING id, content, corresponding_question")
+            .bind(answer.id.0)
+            .bind(answer.content)
+            .bind(answer.question_id.0)
+            .bind(needs_review)
+            .map(|row: PgRow| Answer {
+                id: AnswerId(row.get("id")),
+                content: row.get("content"),
+                question_id: QuestionId(row.get("corresponding_question")),
+            })
+            .fetch_one(&self.connection)
+            .await {
+                Ok(answer) => Ok(answer),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                },
+            }
+    }
+
+    /// Insert a question keeping the creation time, author name and post id of
+    /// its original source, flagged for review if `needs_review`.
+    /// Returns `None` if a question with that post id was already imported.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn add_question_created_on(
+        &self,
+        new_question: NewQuestion,
+        created_on: NaiveDateTime,
+        author: Option<String>,
+        source_post_id: &str,
+        needs_review: bool,
+    ) -> Result<Option<Question>, Error> {
+        match sqlx::query("INSERT INTO questions (title, content, tags, created_on, author, source_post_id, needs_review)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (source_post_id) DO NOTHING
+        RETURNING id, title, content, tags")
+            .bind(new_question.title)
+            .bind(new_question.content)
+            .bind(new_question.tags)
+            .bind(created_on)
+            .bind(author)
+            .bind(source_post_id)
+            .bind(needs_review)
+            .map(|row: PgRow| Question {
+                id: QuestionId(row.get("id")),
+                title: row.get("title"),
+                content: row.get("content"),
+                tags: row.get("tags"),
+            })
+            .fetch_optional(&self.connection)
             .await {
-                Ok(_) => Ok(true),
+                Ok(question) => Ok(question),
                 Err(e) => {
                     tracing::event!(tracing::Level::ERROR, "{:?}", e);
                     Err(Error::DatabaseQueryError)
@@ -116,16 +404,34 @@ impl Store {
             }
     }
 
-    pub async fn add_answer(&self, new_answer: NewAnswer) -> Result<Answer, Error> {
-        match sqlx::query("INSERT INTO answers (content, question_id) VALUES ($1, $2)")
+    /// Insert an answer keeping the creation time, author name and post id of
+    /// its original source, flagged for review if `needs_review`.
+    /// Returns `None` if an answer with that post id was already imported.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn add_answer_created_on(
+        &self,
+        new_answer: NewAnswer,
+        created_on: NaiveDateTime,
+        author: Option<String>,
+        source_post_id: &str,
+        needs_review: bool,
+    ) -> Result<Option<Answer>, Error> {
+        match sqlx::query("INSERT INTO answers (content, corresponding_question, created_on, author, source_post_id, needs_review)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (source_post_id) DO NOTHING
+        RETURNING id, content, corresponding_question")
             .bind(new_answer.content)
             .bind(new_answer.question_id.0)
+            .bind(created_on)
+            .bind(author)
+            .bind(source_post_id)
+            .bind(needs_review)
             .map(|row: PgRow| Answer {
                 id: AnswerId(row.get("id")),
                 content: row.get("content"),
-                question_id: QuestionId(row.get("question_id")),
+                question_id: QuestionId(row.get("corresponding_question")),
             })
-            .fetch_one(&self.connection)
+            .fetch_optional(&self.connection)
             .await {
                 Ok(answer) => Ok(answer),
                 Err(e) => {
@@ -134,4 +440,846 @@ impl Store {
                 },
             }
     }
+
+    /// The ids of questions imported from their original source, keyed by post id there
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn imported_questions(&self) -> Result<HashMap<String, QuestionId>, Error> {
+        match sqlx::query("SELECT source_post_id, id FROM questions WHERE source_post_id IS NOT NULL")
+            .map(|row: PgRow| (row.get("source_post_id"), QuestionId(row.get("id"))))
+            .fetch_all(&self.connection)
+            .await {
+                Ok(questions) => Ok(questions.into_iter().collect()),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                },
+            }
+    }
+
+    /// The ids of answers imported from their original source, keyed by post id there
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn imported_answers(&self) -> Result<HashMap<String, AnswerId>, Error> {
+        match sqlx::query("SELECT source_post_id, id FROM answers WHERE source_post_id IS NOT NULL")
+            .map(|row: PgRow| (row.get("source_post_id"), AnswerId(row.get("id"))))
+            .fetch_all(&self.connection)
+            .await {
+                Ok(answers) => Ok(answers.into_iter().collect()),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                },
+            }
+    }
+
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn set_accepted_answer(
+        &self,
+        question_id: QuestionId,
+        answer_id: AnswerId,
+    ) -> Result<(), Error> {
+        match sqlx::query("UPDATE questions SET accepted_answer = $1 WHERE id = $2")
+            .bind(answer_id.0)
+            .bind(question_id.0)
+            .execute(&self.connection)
+            .await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                },
+            }
+    }
+
+    /// Move the id sequences past rows that were inserted with explicit ids,
+    /// so later inserts don't collide with them
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn sync_id_sequences(&self) -> Result<(), Error> {
+        for table in ["questions", "answers"] {
+            if let Err(e) = sqlx::query(&sync_sequence_query(table))
+                .execute(&self.connection)
+                .await
+            {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                return Err(Error::DatabaseQueryError);
+            }
+        }
+        Ok(())
+    }
+
+    /// Questions with an id greater than `after`, ordered by id and with
+    /// their answers attached. Used to page through all content for exports.
+    /// Content of every status is included, along with its status and
+    /// review flag, so an import restores it as it was.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn export_page(
+        &self,
+        after: i32,
+        limit: i64,
+    ) -> Result<Vec<QuestionRecord>, Error> {
+        let questions = match sqlx::query("SELECT id, title, content, tags, status, needs_review FROM questions
+        WHERE id > $1 ORDER BY id LIMIT $2")
+            .bind(after)
+            .bind(limit)
+            .map(|row: PgRow| QuestionRecord {
+                id: Some(row.get("id")),
+                title: row.get("title"),
+                content: row.get("content"),
+                tags: row.get("tags"),
+                status: moderation_status(&row),
+                needs_review: row.get("needs_review"),
+                answers: Vec::new(),
+            })
+            .fetch_all(&self.connection)
+            .await {
+                Ok(questions) => questions,
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    return Err(Error::DatabaseQueryError);
+                }
+            };
+
+        let ids: Vec<i32> = questions.iter().filter_map(|q| q.id).collect();
+        let answers = match sqlx::query("SELECT id, content, corresponding_question, status, needs_review FROM answers
+        WHERE corresponding_question = ANY($1) ORDER BY id")
+            .bind(&ids)
+            .map(|row: PgRow| {
+                let answer = AnswerRecord {
+                    id: Some(row.get("id")),
+                    content: row.get("content"),
+                    status: moderation_status(&row),
+                    needs_review: row.get("needs_review"),
+                };
+                (row.get::<i32, _>("corresponding_question"), answer)
+            })
+            .fetch_all(&self.connection)
+            .await {
+                Ok(answers) => answers,
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    return Err(Error::DatabaseQueryError);
+                }
+            };
+
+        let mut answers_by_question: HashMap<i32, Vec<AnswerRecord>> = HashMap::new();
+        for (question_id, answer) in answers {
+            answers_by_question.entry(question_id).or_default().push(answer);
+        }
+
+        Ok(questions
+            .into_iter()
+            .map(|q| QuestionRecord {
+                answers: q.id.and_then(|id| answers_by_question.remove(&id)).unwrap_or_default(),
+                ..q
+            })
+            .collect())
+    }
+
+    /// Take a token from the bucket shared by all instances for `client`
+    /// on `route`. The row is locked while the bucket is refilled, so
+    /// concurrent requests can't spend the same token twice.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn take_rate_limit_token(
+        &self,
+        route: &str,
+        client: &str,
+        limit: Limit,
+    ) -> Result<Result<Quota, u64>, Error> {
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+
+            sqlx::query("INSERT INTO rate_limit_buckets (route, client, tokens) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING")
+                .bind(route)
+                .bind(client)
+                .bind(limit.capacity as f64)
+                .execute(&mut tx)
+                .await?;
+
+            let row = sqlx::query("SELECT tokens, EXTRACT(EPOCH FROM NOW() - updated_at)::float8 AS elapsed
+            FROM rate_limit_buckets WHERE route = $1 AND client = $2 FOR UPDATE")
+                .bind(route)
+                .bind(client)
+                .fetch_one(&mut tx)
+                .await?;
+            let (decision, tokens) = limit.take(row.get("tokens"), row.get("elapsed"));
+
+            sqlx::query("UPDATE rate_limit_buckets SET tokens = $3, updated_at = NOW()
+            WHERE route = $1 AND client = $2")
+                .bind(route)
+                .bind(client)
+                .bind(tokens)
+                .execute(&mut tx)
+                .await?;
+
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(decision)
+        };
+
+        match result.await {
+            Ok(decision) => Ok(decision),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Delete the shared buckets which have refilled completely, as they
+    /// weren't touched for the whole period of their route's limit, and
+    /// those of routes which are no longer limited
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn sweep_rate_limit_buckets(&self, rules: &[RateLimitRule]) -> Result<u64, Error> {
+        let routes: Vec<&str> = rules.iter().map(|rule| rule.route.as_str()).collect();
+        let seconds: Vec<f64> = rules.iter().map(|rule| rule.limit.per.as_secs_f64()).collect();
+
+        match sqlx::query("DELETE FROM rate_limit_buckets b WHERE NOT EXISTS (
+            SELECT 1 FROM UNNEST($1::text[], $2::float8[]) AS l(route, seconds)
+            WHERE l.route = b.route AND b.updated_at > NOW() - make_interval(secs => l.seconds))")
+            .bind(routes)
+            .bind(seconds)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Write a batch of imported records, each paired with its line number,
+    /// in a single transaction. Every record runs in its own savepoint so a
+    /// failing one is reported without discarding the rest of the batch.
+    /// On a dry run the transaction is rolled back instead of committed.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn import_questions(
+        &self,
+        records: Vec<(usize, QuestionRecord)>,
+        dry_run: bool,
+    ) -> Result<ImportReport, Error> {
+        let mut report = ImportReport { dry_run, ..ImportReport::default() };
+
+        let mut tx = match self.connection.begin().await {
+            Ok(tx) => tx,
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                return Err(Error::DatabaseQueryError);
+            }
+        };
+
+        for (line, record) in records {
+            let answers = record.answers.len();
+            match import_record(&mut tx, record, dry_run).await {
+                Ok(()) => {
+                    report.questions += 1;
+                    report.answers += answers;
+                }
+                Err(e) => report.errors.push(ImportLineError {
+                    line,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        let result = if dry_run {
+            tx.rollback().await
+        } else {
+            tx.commit().await
+        };
+
+        match result {
+            Ok(()) => Ok(report),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Insert a question hidden until a moderation worker approves it
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn queue_question(&self, new_question: NewQuestion) -> Result<Question, Error> {
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+
+            let question = sqlx::query("INSERT INTO questions (title, content, tags, status) VALUES ($1, $2, $3, $4)
+            RETURNING id, title, content, tags")
+                .bind(new_question.title)
+                .bind(new_question.content)
+                .bind(new_question.tags)
+                .bind(ModerationStatus::PendingModeration.as_str())
+                .map(|row: PgRow| Question {
+                    id: QuestionId(row.get("id")),
+                    title: row.get("title"),
+                    content: row.get("content"),
+                    tags: row.get("tags"),
+                })
+                .fetch_one(&mut tx)
+                .await?;
+
+            sqlx::query("INSERT INTO moderation_jobs (question_id, route) VALUES ($1, 'add_question')")
+                .bind(question.id.0)
+                .execute(&mut tx)
+                .await?;
+
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(question)
+        };
+
+        match result.await {
+            Ok(question) => Ok(question),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Update a question and hide it until a moderation worker approves the new text
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn queue_question_update(
+        &self,
+        question: Question,
+        question_id: i32,
+    ) -> Result<Question, Error> {
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+
+            let question = sqlx::query("UPDATE questions SET title = $1, content = $2, tags = $3, status = $4
+            WHERE id = $5
+            RETURNING id, title, content, tags")
+                .bind(question.title)
+                .bind(question.content)
+                .bind(question.tags)
+                .bind(ModerationStatus::PendingModeration.as_str())
+                .bind(question_id)
+                .map(|row: PgRow| Question {
+                    id: QuestionId(row.get("id")),
+                    title: row.get("title"),
+                    content: row.get("content"),
+                    tags: row.get("tags"),
+                })
+                .fetch_one(&mut tx)
+                .await?;
+
+            sqlx::query("INSERT INTO moderation_jobs (question_id, route) VALUES ($1, 'update_question')")
+                .bind(question.id.0)
+                .execute(&mut tx)
+                .await?;
+
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(question)
+        };
+
+        match result.await {
+            Ok(question) => Ok(question),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Insert an answer hidden until a moderation worker approves it
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn queue_answer(&self, new_answer: NewAnswer) -> Result<Answer, Error> {
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+
+            let answer = sqlx::query("INSERT INTO answers (content, corresponding_question, status) VALUES ($1, $2, $3)
+            RETURNING id, content, corresponding_question")
+                .bind(new_answer.content)
+                .bind(new_answer.question_id.0)
+                .bind(ModerationStatus::PendingModeration.as_str())
+                .map(|row: PgRow| Answer {
+                    id: AnswerId(row.get("id")),
+                    content: row.get("content"),
+                    question_id: QuestionId(row.get("corresponding_question")),
+                })
+                .fetch_one(&mut tx)
+                .await?;
+
+            sqlx::query("INSERT INTO moderation_jobs (answer_id, route) VALUES ($1, 'add_answer')")
+                .bind(answer.id.0)
+                .execute(&mut tx)
+                .await?;
+
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(answer)
+        };
+
+        match result.await {
+            Ok(answer) => Ok(answer),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Claim the next due moderation job, skipping jobs other workers hold.
+    /// The job is leased rather than locked: it becomes due again after
+    /// `lease`, so a worker that dies mid-job doesn't lose it.
+    // Polled continuously, so only traced at debug level
+    #[instrument(level = "debug", skip_all, fields(db.system = "postgresql"))]
+    pub async fn claim_moderation_job(
+        &self,
+        lease: std::time::Duration,
+    ) -> Result<Option<ModerationJob>, Error> {
+        match sqlx::query("WITH claimed AS (
+            UPDATE moderation_jobs SET attempts = attempts + 1, run_at = NOW() + $1 * INTERVAL '1 second'
+            WHERE id = (
+                SELECT id FROM moderation_jobs
+                WHERE dead_at IS NULL AND run_at <= NOW()
+                ORDER BY run_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, attempts, route, question_id, answer_id
+        )
+        SELECT claimed.id, claimed.attempts, claimed.route, claimed.question_id, claimed.answer_id,
+            questions.title, questions.content AS question_content, answers.content AS answer_content
+        FROM claimed
+        LEFT JOIN questions ON questions.id = claimed.question_id
+        LEFT JOIN answers ON answers.id = claimed.answer_id")
+            .bind(lease.as_secs_f64())
+            .map(|row: PgRow| {
+                let target = match row.get::<Option<i32>, _>("question_id") {
+                    Some(id) => ModerationTarget::Question {
+                        id: QuestionId(id),
+                        title: row.get("title"),
+                        content: row.get("question_content"),
+                    },
+                    None => ModerationTarget::Answer {
+                        id: AnswerId(row.get("answer_id")),
+                        content: row.get("answer_content"),
+                    },
+                };
+                ModerationJob {
+                    id: row.get("id"),
+                    attempts: row.get("attempts"),
+                    route: row.get("route"),
+                    target,
+                }
+            })
+            .fetch_optional(&self.connection)
+            .await {
+                Ok(job) => Ok(job),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                }
+            }
+    }
+
+    /// Apply a worker's decision, keep the findings of the profanity check
+    /// in `audit`, and remove the job from the queue, all at once, so a
+    /// retried job doesn't audit the same text twice.
+    /// If the text was edited since the job was claimed, it is left alone;
+    /// the edit queued a job of its own. Approved content is published.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn finish_moderation_job(
+        &self,
+        job: &ModerationJob,
+        outcome: ModerationOutcome,
+        audit: Option<&Review>,
+    ) -> Result<(), Error> {
+        let (status, new_title, new_content, needs_review) = match outcome {
+            ModerationOutcome::Approve { title, content, needs_review } => {
+                (ModerationStatus::Approved, title, Some(content), needs_review)
+            }
+            ModerationOutcome::Reject => (ModerationStatus::Rejected, None, None, false),
+        };
+
+        let approved = status == ModerationStatus::Approved;
+
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+
+            let event = match &job.target {
+                ModerationTarget::Question { id, title, content } => {
+                    let question = sqlx::query("UPDATE questions
+                    SET title = COALESCE($1, title), content = COALESCE($2, content),
+                        status = $3, needs_review = $4
+                    WHERE id = $5 AND title = $6 AND content = $7
+                    RETURNING id, title, content, tags")
+                        .bind(new_title)
+                        .bind(new_content)
+                        .bind(status.as_str())
+                        .bind(needs_review)
+                        .bind(id.0)
+                        .bind(title)
+                        .bind(content)
+                        .map(|row: PgRow| Question {
+                            id: QuestionId(row.get("id")),
+                            title: row.get("title"),
+                            content: row.get("content"),
+                            tags: row.get("tags"),
+                        })
+                        .fetch_optional(&mut tx)
+                        .await?;
+                    question.map(|question| match job.route.as_str() {
+                        "update_question" => Event::QuestionUpdated { question },
+                        _ => Event::QuestionCreated { question },
+                    })
+                }
+                ModerationTarget::Answer { id, content } => {
+                    sqlx::query("UPDATE answers
+                    SET content = COALESCE($1, content), status = $2, needs_review = $3
+                    WHERE id = $4 AND content = $5
+                    RETURNING id, content, corresponding_question,
+                        (SELECT tags FROM questions WHERE id = corresponding_question) AS tags")
+                        .bind(new_content)
+                        .bind(status.as_str())
+                        .bind(needs_review)
+                        .bind(id.0)
+                        .bind(content)
+                        .map(|row: PgRow| Event::AnswerCreated {
+                            answer: Answer {
+                                id: AnswerId(row.get("id")),
+                                content: row.get("content"),
+                                question_id: QuestionId(row.get("corresponding_question")),
+                            },
+                            tags: row.get("tags"),
+                        })
+                        .fetch_optional(&mut tx)
+                        .await?
+                }
+            };
+
+            if let Some(review) = audit {
+                let content_id = match &job.target {
+                    ModerationTarget::Question { id, .. } => ContentId::Question(id.clone()),
+                    ModerationTarget::Answer { id, .. } => ContentId::Answer(id.clone()),
+                };
+                insert_profanity_audit(&mut tx, review, Some(content_id)).await?;
+            }
+
+            sqlx::query("DELETE FROM moderation_jobs WHERE id = $1")
+                .bind(job.id)
+                .execute(&mut tx)
+                .await?;
+
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(event.filter(|_| approved))
+        };
+
+        match result.await {
+            Ok(event) => {
+                if let Some(event) = event {
+                    self.publish(event).await;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Keep what the profanity check found in a rejected submission.
+    /// Stored content is audited together with the content itself.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn audit_profanity(&self, review: &Review) -> Result<(), Error> {
+        let result = async {
+            let mut tx = self.connection.begin().await?;
+            insert_profanity_audit(&mut tx, review, None).await?;
+            tx.commit().await
+        };
+
+        match result.await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Record a failed attempt at a moderation job. It runs again after
+    /// `retry_in`, or is dead-lettered for an operator to look at when `None`.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn fail_moderation_job(
+        &self,
+        job_id: i32,
+        error: &str,
+        retry_in: Option<std::time::Duration>,
+    ) -> Result<(), Error> {
+        let query = match retry_in {
+            Some(delay) => sqlx::query("UPDATE moderation_jobs
+            SET last_error = $2, run_at = NOW() + $3 * INTERVAL '1 second'
+            WHERE id = $1")
+                .bind(job_id)
+                .bind(error)
+                .bind(delay.as_secs_f64()),
+            None => sqlx::query("UPDATE moderation_jobs SET last_error = $2, dead_at = NOW() WHERE id = $1")
+                .bind(job_id)
+                .bind(error),
+        };
+
+        match query.execute(&self.connection).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Claim `key` of `client` for a request to `route` whose body hashes
+    /// to `fingerprint`. A key older than `ttl` is taken over, as if it
+    /// had been dropped already.
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn claim_idempotency_key(
+        &self,
+        route: &str,
+        client: &str,
+        key: &str,
+        fingerprint: &[u8],
+        ttl: std::time::Duration,
+    ) -> Result<Claim, Error> {
+        let result = async {
+            let inserted = sqlx::query("INSERT INTO idempotency_keys (route, client, key, fingerprint) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (route, client, key) DO UPDATE SET fingerprint = EXCLUDED.fingerprint,
+                status = NULL, content_type = NULL, location = NULL, body = NULL, created_at = NOW()
+            WHERE idempotency_keys.created_at < NOW() - make_interval(secs => $5)")
+                .bind(route)
+                .bind(client)
+                .bind(key)
+                .bind(fingerprint)
+                .bind(ttl.as_secs_f64())
+                .execute(&self.connection)
+                .await?
+                .rows_affected();
+            if inserted == 1 {
+                return Ok(Claim::Claimed);
+            }
+
+            let row = sqlx::query("SELECT fingerprint, status, content_type, location, body
+            FROM idempotency_keys WHERE route = $1 AND client = $2 AND key = $3")
+                .bind(route)
+                .bind(client)
+                .bind(key)
+                .fetch_optional(&self.connection)
+                .await?;
+
+            Ok::<_, sqlx::Error>(match row {
+                // Released by a failed attempt since the insert
+                None => Claim::InProgress,
+                Some(row) if row.get::<Vec<u8>, _>("fingerprint") != fingerprint => Claim::Mismatch,
+                Some(row) => match row.get::<Option<i16>, _>("status") {
+                    None => Claim::InProgress,
+                    Some(status) => Claim::Replay(Recorded {
+                        status: StatusCode::from_u16(status as u16)
+                            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                        content_type: row.get::<Option<String>, _>("content_type").unwrap_or_default(),
+                        location: row.get("location"),
+                        body: row.get::<Option<Vec<u8>>, _>("body").unwrap_or_default(),
+                    }),
+                },
+            })
+        };
+
+        match result.await {
+            Ok(claim) => Ok(claim),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError)
+            }
+        }
+    }
+
+    /// Keep the response to the request holding `key`, to replay it to retries
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn record_idempotent_response(
+        &self,
+        route: &str,
+        client: &str,
+        key: &str,
+        response: &Recorded,
+    ) -> Result<(), Error> {
+        match sqlx::query("UPDATE idempotency_keys SET status = $4, content_type = $5, location = $6, body = $7
+        WHERE route = $1 AND client = $2 AND key = $3")
+            .bind(route)
+            .bind(client)
+            .bind(key)
+            .bind(response.status.as_u16() as i16)
+            .bind(&response.content_type)
+            .bind(&response.location)
+            .bind(&response.body)
+            .execute(&self.connection)
+            .await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                }
+            }
+    }
+
+    /// Give up `key` after its request failed, so a retry can claim it
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn release_idempotency_key(&self, route: &str, client: &str, key: &str) -> Result<(), Error> {
+        match sqlx::query("DELETE FROM idempotency_keys WHERE route = $1 AND client = $2 AND key = $3 AND status IS NULL")
+            .bind(route)
+            .bind(client)
+            .bind(key)
+            .execute(&self.connection)
+            .await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                }
+            }
+    }
+
+    /// Delete the keys older than `ttl`, whose responses are no longer replayed
+    #[instrument(skip_all, fields(db.system = "postgresql"))]
+    pub async fn sweep_idempotency_keys(&self, ttl: std::time::Duration) -> Result<u64, Error> {
+        match sqlx::query("DELETE FROM idempotency_keys WHERE created_at < NOW() - make_interval(secs => $1)")
+            .bind(ttl.as_secs_f64())
+            .execute(&self.connection)
+            .await {
+                Ok(result) => Ok(result.rows_affected()),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                    Err(Error::DatabaseQueryError)
+                }
+            }
+    }
+}
+
+/// Keep what the profanity check found in `review`, for fields where
+/// anything was found. `content` is the stored question or answer,
+/// if the submission wasn't rejected.
+async fn insert_profanity_audit(
+    tx: &mut Transaction<'_, Postgres>,
+    review: &Review,
+    content: Option<ContentId>,
+) -> Result<(), sqlx::Error> {
+    let (question_id, answer_id) = match content {
+        Some(ContentId::Question(id)) => (Some(id.0), None),
+        Some(ContentId::Answer(id)) => (None, Some(id.0)),
+        None => (None, None),
+    };
+    let request_id = handle_errors::current_request_id();
+
+    for field in review.fields.iter().filter(|f| !f.detection.bad_words.is_empty()) {
+        sqlx::query("INSERT INTO profanity_audit
+        (route, field, policy, question_id, answer_id, request_id, original, censored, bad_words_total, bad_words)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)")
+            .bind(&review.route)
+            .bind(field.field)
+            .bind(field.policy.as_str())
+            .bind(question_id)
+            .bind(answer_id)
+            .bind(&request_id)
+            .bind(&field.detection.original)
+            .bind(&field.detection.censored)
+            .bind(field.detection.bad_words.len() as i32)
+            .bind(sqlx::types::Json(&field.detection.bad_words))
+            .execute(&mut *tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// The `status` column of a question or answer row
+fn moderation_status(row: &PgRow) -> ModerationStatus {
+    // The column is checked to hold one of the statuses. Anything else is
+    // kept hidden rather than shown.
+    row.get::<String, _>("status").parse().unwrap_or(ModerationStatus::Rejected)
+}
+
+fn sync_sequence_query(table: &str) -> String {
+    format!(
+        "SELECT setval(pg_get_serial_sequence('{0}', 'id'), GREATEST(MAX(id), 1), MAX(id) IS NOT NULL) FROM {0}",
+        table
+    )
+}
+
+/// Upsert or insert one question and its answers inside a savepoint,
+/// with the status and review flag they were exported with. Content
+/// still pending is queued for moderation again, unless it already is.
+/// Sequences aren't transactional, so a dry run leaves them alone: rows
+/// without an id are numbered past the largest id instead of drawing
+/// from the sequence, and the sequences aren't moved past explicit ids.
+async fn import_record(
+    tx: &mut Transaction<'_, Postgres>,
+    record: QuestionRecord,
+    dry_run: bool,
+) -> Result<(), sqlx::Error> {
+    let mut savepoint = tx.begin().await?;
+    let explicit_ids = record.id.is_some() || record.answers.iter().any(|a| a.id.is_some());
+
+    let question_id: i32 = match (record.id, dry_run) {
+        (Some(id), _) => sqlx::query("INSERT INTO questions (id, title, content, tags, status, needs_review)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (id) DO UPDATE SET title = $2, content = $3, tags = $4, status = $5, needs_review = $6
+        RETURNING id")
+            .bind(id),
+        (None, false) => sqlx::query("INSERT INTO questions (title, content, tags, status, needs_review)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id"),
+        (None, true) => sqlx::query("INSERT INTO questions (id, title, content, tags, status, needs_review)
+        VALUES ((SELECT COALESCE(MAX(id), 0) + 1 FROM questions), $1, $2, $3, $4, $5)
+        RETURNING id"),
+    }
+        .bind(record.title)
+        .bind(record.content)
+        .bind(record.tags)
+        .bind(record.status.as_str())
+        .bind(record.needs_review)
+        .fetch_one(&mut savepoint)
+        .await?
+        .get("id");
+    if record.status == ModerationStatus::PendingModeration {
+        sqlx::query("INSERT INTO moderation_jobs (question_id, route) SELECT $1, 'add_question'
+        WHERE NOT EXISTS (SELECT 1 FROM moderation_jobs WHERE question_id = $1)")
+            .bind(question_id)
+            .execute(&mut savepoint)
+            .await?;
+    }
+
+    for answer in record.answers {
+        let answer_id: i32 = match (answer.id, dry_run) {
+            (Some(id), _) => sqlx::query("INSERT INTO answers (id, content, corresponding_question, status, needs_review)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (id) DO UPDATE SET content = $2, corresponding_question = $3, status = $4, needs_review = $5
+            RETURNING id")
+                .bind(id),
+            (None, false) => sqlx::query("INSERT INTO answers (content, corresponding_question, status, needs_review)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id"),
+            (None, true) => sqlx::query("INSERT INTO answers (id, content, corresponding_question, status, needs_review)
+            VALUES ((SELECT COALESCE(MAX(id), 0) + 1 FROM answers), $1, $2, $3, $4)
+            RETURNING id"),
+        }
+            .bind(answer.content)
+            .bind(question_id)
+            .bind(answer.status.as_str())
+            .bind(answer.needs_review)
+            .fetch_one(&mut savepoint)
+            .await?
+            .get("id");
+        if answer.status == ModerationStatus::PendingModeration {
+            sqlx::query("INSERT INTO moderation_jobs (answer_id, route) SELECT $1, 'add_answer'
+            WHERE NOT EXISTS (SELECT 1 FROM moderation_jobs WHERE answer_id = $1)")
+                .bind(answer_id)
+                .execute(&mut savepoint)
+                .await?;
+        }
+    }
+
+    // Keep later records without an id from colliding with this one
+    if explicit_ids && !dry_run {
+        for table in ["questions", "answers"] {
+            sqlx::query(&sync_sequence_query(table)).execute(&mut savepoint).await?;
+        }
+    }
+
+    savepoint.commit().await
 }
@@ -0,0 +1,93 @@
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::Context;
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use warp::http::{header::HeaderName, HeaderMap, HeaderValue};
+
+use crate::cli::OtlpProtocol;
+
+/// Build a tracer provider exporting spans in batches to an OTLP collector,
+/// and make W3C `traceparent` headers the propagation format
+pub fn tracer_provider(endpoint: &str, protocol: OtlpProtocol) -> SdkTracerProvider {
+    let exporter = match protocol {
+        OtlpProtocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build(),
+        OtlpProtocol::HttpProtobuf => SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(http_traces_url(endpoint))
+            .build(),
+    }
+    .expect("Cannot create OTLP exporter");
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(env!("CARGO_PKG_NAME"))
+                .build(),
+        )
+        .build()
+}
+
+/// The HTTP exporter expects the full signal URL, while the usual
+/// configuration names the collector's base URL
+fn http_traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint)
+    }
+}
+
+/// Continue the trace a client started, if its request carries a `traceparent`
+pub fn set_remote_parent(span: &tracing::Span, headers: &HeaderMap) {
+    let parent: Context = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    // Fails only when no OpenTelemetry layer is installed, i.e. export is off
+    let _ = span.set_parent(parent);
+}
+
+/// Headers carrying the current span's trace context to an upstream service
+pub fn trace_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use parking_lot::RwLock;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+
+/// The certificate chain and private key served over TLS, loaded from
+/// PEM files. They can be reloaded while serving: new connections get the
+/// new certificate, established ones keep theirs.
+#[derive(Debug)]
+pub struct Certificates {
+    cert: PathBuf,
+    key: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl Certificates {
+    pub fn load(cert: &Path, key: &Path) -> Result<Arc<Self>, String> {
+        let provider = Arc::new(ring::default_provider());
+        let current = read(cert, key, &provider)?;
+
+        Ok(Arc::new(Certificates {
+            cert: cert.to_path_buf(),
+            key: key.to_path_buf(),
+            provider,
+            current: RwLock::new(Arc::new(current)),
+        }))
+    }
+
+    /// Read the files again. The certificate being served is kept if they
+    /// can't be read or don't match, e.g. while they're being replaced.
+    pub fn reload(&self) -> Result<(), String> {
+        let next = read(&self.cert, &self.key, &self.provider)?;
+        *self.current.write() = Arc::new(next);
+        Ok(())
+    }
+
+    /// Accepts TLS connections with whatever certificate is current
+    pub fn acceptor(self: &Arc<Self>) -> Result<TlsAcceptor, String> {
+        let mut config = ServerConfig::builder_with_provider(self.provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+
+    /// Reload on SIGHUP, and whenever the files changed when checked every
+    /// `interval`, until `stop` is cancelled
+    pub fn watch(
+        self: Arc<Self>,
+        interval: Duration,
+        stop: CancellationToken,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .expect("Cannot listen for SIGHUP");
+            let mut loaded = self.modified();
+            let mut ticks = tokio::time::interval(interval);
+
+            loop {
+                #[cfg(unix)]
+                let hangup = hangup.recv();
+                #[cfg(not(unix))]
+                let hangup = std::future::pending::<Option<()>>();
+
+                let hung_up = tokio::select! {
+                    _ = stop.cancelled() => return,
+                    _ = hangup => true,
+                    _ = ticks.tick() => false,
+                };
+                let modified = self.modified();
+                if !hung_up && modified == loaded {
+                    continue;
+                }
+
+                // Files which fail to load are tried again on the next tick
+                match self.reload() {
+                    Ok(()) => {
+                        loaded = modified;
+                        tracing::event!(
+                            tracing::Level::INFO,
+                            "Reloaded TLS certificate after {}",
+                            if hung_up { "SIGHUP" } else { "its files changed" }
+                        );
+                    }
+                    Err(e) => tracing::event!(
+                        tracing::Level::ERROR,
+                        "Cannot reload TLS certificate, still serving the previous one: {}",
+                        e
+                    ),
+                }
+            }
+        })
+    }
+
+    fn modified(&self) -> [Option<SystemTime>; 2] {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        [modified(&self.cert), modified(&self.key)]
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().clone())
+    }
+}
+
+fn read(cert: &Path, key: &Path, provider: &CryptoProvider) -> Result<CertifiedKey, String> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{}: {}", cert.display(), e))?;
+    if chain.is_empty() {
+        return Err(format!("{}: no certificate found", cert.display()));
+    }
+    let private_key =
+        PrivateKeyDer::from_pem_file(key).map_err(|e| format!("{}: {}", key.display(), e))?;
+
+    CertifiedKey::from_der(chain, private_key, provider)
+        .map_err(|e| format!("Cannot use {} with {}: {}", key.display(), cert.display(), e))
+}
@@ -0,0 +1,56 @@
+use std::str::FromStr;
+
+/// Routes whose request body size can be limited
+pub const ROUTES: &[&str] = &["add_question", "update_question", "add_answer", "import"];
+
+/// Largest request body accepted by one route, given on the command line
+/// as `ROUTE=BYTES`. The size may end in `KiB`, `MiB` or `GiB`.
+#[derive(Debug, Clone)]
+pub struct BodyLimitRule {
+    pub route: String,
+    pub bytes: u64,
+}
+
+impl FromStr for BodyLimitRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected ROUTE=BYTES, got `{}`", s);
+
+        let (route, size) = s.split_once('=').ok_or_else(invalid)?;
+        let size = size.trim();
+        let (number, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+            Some(i) => size.split_at(i),
+            None => (size, ""),
+        };
+        let unit: u64 = match unit.trim() {
+            "" => 1,
+            "KiB" => 1 << 10,
+            "MiB" => 1 << 20,
+            "GiB" => 1 << 30,
+            _ => return Err(invalid()),
+        };
+        let bytes = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+            .ok_or_else(invalid)?;
+
+        if route.trim().is_empty() || bytes == 0 {
+            return Err(invalid());
+        }
+        // A misspelt route would otherwise keep the default limit without notice
+        if !ROUTES.contains(&route.trim()) {
+            return Err(format!(
+                "unknown route `{}`, expected one of {}",
+                route.trim(),
+                ROUTES.join(", ")
+            ));
+        }
+
+        Ok(BodyLimitRule {
+            route: route.trim().to_string(),
+            bytes,
+        })
+    }
+}
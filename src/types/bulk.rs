@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::types::moderation::ModerationStatus;
+
+/// A question together with its answers, as used by fixture files and
+/// the JSON Lines import/export.
+/// Records with an `id` are upserted, records without one are inserted.
+/// Records without a `status` are approved.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct QuestionRecord {
+    pub id: Option<i32>,
+    pub title: String,
+    pub content: String,
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub status: ModerationStatus,
+    #[serde(default)]
+    pub needs_review: bool,
+    #[serde(default)]
+    pub answers: Vec<AnswerRecord>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct AnswerRecord {
+    pub id: Option<i32>,
+    pub content: String,
+    #[serde(default)]
+    pub status: ModerationStatus,
+    #[serde(default)]
+    pub needs_review: bool,
+}
+
+/// Query parameters of `POST /admin/import`
+#[derive(Debug, Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportParams {
+    /// Validate and write everything, but roll back instead of committing
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Outcome of an import, returned as the response body
+#[derive(Debug, Serialize, Default, ToSchema)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub questions: usize,
+    pub answers: usize,
+    pub errors: Vec<ImportLineError>,
+}
+
+impl ImportReport {
+    pub fn merge(&mut self, other: ImportReport) {
+        self.questions += other.questions;
+        self.answers += other.answers;
+        self.errors.extend(other.errors);
+    }
+}
+
+/// A line of the import which could not be written
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportLineError {
+    pub line: usize,
+    pub error: String,
+}
@@ -0,0 +1,77 @@
+use std::str::FromStr;
+
+/// An origin browsers may make cross-origin requests from, given on the
+/// command line as `*`, `SCHEME://HOST[:PORT]`, or `SCHEME://*.DOMAIN[:PORT]`
+/// for every subdomain of a domain, at any depth, but not the domain itself
+#[derive(Debug, Clone, PartialEq)]
+pub enum CorsOrigin {
+    Any,
+    Exact(String),
+    Subdomains {
+        /// `SCHEME://`
+        prefix: String,
+        /// `.DOMAIN[:PORT]`
+        suffix: String,
+    },
+}
+
+impl CorsOrigin {
+    pub fn matches(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        match self {
+            CorsOrigin::Any => true,
+            CorsOrigin::Exact(allowed) => origin == *allowed,
+            CorsOrigin::Subdomains { prefix, suffix } => origin
+                .strip_prefix(prefix.as_str())
+                .and_then(|rest| rest.strip_suffix(suffix.as_str()))
+                .is_some_and(|subdomain| {
+                    subdomain.split('.').all(|label| {
+                        !label.is_empty()
+                            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    })
+                }),
+        }
+    }
+}
+
+impl FromStr for CorsOrigin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "expected *, SCHEME://HOST[:PORT] or SCHEME://*.DOMAIN[:PORT], got `{}`",
+                s
+            )
+        };
+
+        let s = s.trim();
+        if s == "*" {
+            return Ok(CorsOrigin::Any);
+        }
+
+        // Origins sent by browsers are lowercase and have no path
+        let origin = s.to_ascii_lowercase();
+        let (scheme, host) = origin.split_once("://").ok_or_else(invalid)?;
+        let valid = |part: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | ':' | '[' | ']'))
+        };
+        if !valid(scheme) || scheme.contains(['.', ':', '[', ']']) {
+            return Err(invalid());
+        }
+
+        match host.strip_prefix('*') {
+            Some(domain) if domain.starts_with('.') && valid(&domain[1..]) => {
+                Ok(CorsOrigin::Subdomains {
+                    prefix: format!("{}://", scheme),
+                    suffix: domain.to_string(),
+                })
+            }
+            None if valid(host) => Ok(CorsOrigin::Exact(origin)),
+            _ => Err(invalid()),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::types::answer::Answer;
+use crate::types::question::{Question, QuestionId};
+
+/// A change to a visible question or answer, pushed to WebSocket
+/// subscribers. With async moderation, it's published once approved.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    QuestionCreated {
+        question: Question,
+    },
+    QuestionUpdated {
+        question: Question,
+    },
+    QuestionDeleted {
+        question_id: QuestionId,
+        tags: Option<Vec<String>>,
+    },
+    AnswerCreated {
+        answer: Answer,
+        /// Tags of the question answered
+        tags: Option<Vec<String>>,
+    },
+}
+
+impl Event {
+    pub fn question_id(&self) -> &QuestionId {
+        match self {
+            Event::QuestionCreated { question } | Event::QuestionUpdated { question } => {
+                &question.id
+            }
+            Event::QuestionDeleted { question_id, .. } => question_id,
+            Event::AnswerCreated { answer, .. } => &answer.question_id,
+        }
+    }
+
+    pub fn tags(&self) -> &[String] {
+        let tags = match self {
+            Event::QuestionCreated { question } | Event::QuestionUpdated { question } => {
+                &question.tags
+            }
+            Event::QuestionDeleted { tags, .. } | Event::AnswerCreated { tags, .. } => tags,
+        };
+        tags.as_deref().unwrap_or_default()
+    }
+}
+
+/// Events a WebSocket client can subscribe to: `"all"`, `{"tag": "rust"}`
+/// or `{"question": 1}`. Tags and questions include their answers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    All,
+    Tag(String),
+    Question(QuestionId),
+}
+
+impl Topic {
+    pub fn matches(&self, event: &Event) -> bool {
+        match self {
+            Topic::All => true,
+            Topic::Tag(tag) => event.tags().contains(tag),
+            Topic::Question(id) => event.question_id() == id,
+        }
+    }
+}
+
+/// What WebSocket clients send
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe { topic: Topic },
+    Unsubscribe { topic: Topic },
+}
+
+/// What WebSocket clients are sent besides events
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Subscribed { topic: Topic },
+    Unsubscribed { topic: Topic },
+    /// The client read too slowly, and this many events were dropped
+    Lagged { missed: u64 },
+    Error { message: String },
+}
@@ -0,0 +1,29 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Up,
+    Degraded,
+    Down,
+}
+
+/// Result of probing a single dependency
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Check {
+    pub status: Status,
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response body of the health endpoints
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthReport {
+    pub status: Status,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<&'static str, Check>,
+}
@@ -0,0 +1,62 @@
+use warp::http::{header, HeaderValue, StatusCode};
+use warp::hyper::Body;
+
+/// A response kept for replaying it to retries of its request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recorded {
+    pub status: StatusCode,
+    pub content_type: String,
+    pub location: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// What became of an attempt to use an idempotency key
+#[derive(Debug)]
+pub enum Claim {
+    /// The key is new, or expired, and the request may be handled
+    Claimed,
+    /// A request with the same key and body was already handled
+    Replay(Recorded),
+    /// A request with the same key is still being handled
+    InProgress,
+    /// The key was used for a request with another body
+    Mismatch,
+}
+
+impl Recorded {
+    /// `201 Created` with the stored resource at `location`
+    pub fn created(content_type: &str, location: String, body: Vec<u8>) -> Self {
+        Recorded {
+            status: StatusCode::CREATED,
+            content_type: content_type.to_string(),
+            location: Some(location),
+            body,
+        }
+    }
+
+    /// `202 Accepted` with the resource queued for moderation at `location`
+    pub fn accepted(content_type: &str, location: String, body: Vec<u8>) -> Self {
+        Recorded {
+            status: StatusCode::ACCEPTED,
+            ..Recorded::created(content_type, location, body)
+        }
+    }
+}
+
+impl warp::Reply for Recorded {
+    fn into_response(self) -> warp::reply::Response {
+        let mut res = warp::reply::Response::new(Body::from(self.body));
+        *res.status_mut() = self.status;
+        let headers = res.headers_mut();
+        // Both were valid header values when the response was recorded
+        if let Ok(content_type) = HeaderValue::from_str(&self.content_type) {
+            headers.insert(header::CONTENT_TYPE, content_type);
+        }
+        if let Some(Ok(location)) = self.location.as_deref().map(HeaderValue::from_str) {
+            headers.insert(header::LOCATION, location);
+        }
+        // Recorded bodies are in the format the client accepted
+        headers.insert(header::VARY, HeaderValue::from_static("accept"));
+        res
+    }
+}
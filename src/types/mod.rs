@@ -1,3 +1,13 @@
 pub mod answer;
+pub mod body_limit;
+pub mod bulk;
+pub mod cors;
+pub mod event;
+pub mod health;
+pub mod idempotency;
+pub mod moderation;
+pub mod network;
 pub mod pagination;
+pub mod policy;
 pub mod question;
+pub mod rate_limit;
@@ -0,0 +1,84 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::types::{answer::AnswerId, question::QuestionId};
+
+/// Visibility of a question or answer. Only approved content is listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationStatus {
+    PendingModeration,
+    #[default]
+    Approved,
+    Rejected,
+}
+
+impl FromStr for ModerationStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending_moderation" => Ok(ModerationStatus::PendingModeration),
+            "approved" => Ok(ModerationStatus::Approved),
+            "rejected" => Ok(ModerationStatus::Rejected),
+            _ => Err(format!("unknown moderation status `{}`", s)),
+        }
+    }
+}
+
+impl ModerationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModerationStatus::PendingModeration => "pending_moderation",
+            ModerationStatus::Approved => "approved",
+            ModerationStatus::Rejected => "rejected",
+        }
+    }
+}
+
+/// Stored text a moderation job has to check
+#[derive(Debug, Clone)]
+pub enum ModerationTarget {
+    Question {
+        id: QuestionId,
+        title: String,
+        content: String,
+    },
+    Answer {
+        id: AnswerId,
+        content: String,
+    },
+}
+
+/// A stored question or answer
+#[derive(Debug, Clone)]
+pub enum ContentId {
+    Question(QuestionId),
+    Answer(AnswerId),
+}
+
+/// A job claimed from the moderation queue
+#[derive(Debug, Clone)]
+pub struct ModerationJob {
+    pub id: i32,
+    /// Including the current one
+    pub attempts: i32,
+    /// Route the content was submitted to, which decides the policies applied
+    pub route: String,
+    pub target: ModerationTarget,
+}
+
+/// What a worker decided about a job's target
+#[derive(Debug, Clone)]
+pub enum ModerationOutcome {
+    /// Store the checked text and make it visible
+    Approve {
+        title: Option<String>,
+        content: String,
+        needs_review: bool,
+    },
+    /// Keep the text hidden
+    Reject,
+}
@@ -0,0 +1,61 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A range of IP addresses, given on the command line as `ADDR/PREFIX`,
+/// or as a single address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// The network of the first `prefix` bits of `addr`. IPv4 addresses
+    /// mapped into IPv6 are taken as the IPv4 addresses they are.
+    pub fn of(addr: IpAddr, prefix: u8) -> Self {
+        let (addr, prefix) = match addr.to_canonical() {
+            IpAddr::V4(v4) => {
+                let prefix = prefix.min(32);
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                (IpAddr::from((u32::from(v4) & mask).to_be_bytes()), prefix)
+            }
+            IpAddr::V6(v6) => {
+                let prefix = prefix.min(128);
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                (IpAddr::from((u128::from(v6) & mask).to_be_bytes()), prefix)
+            }
+        };
+        IpNetwork { addr, prefix }
+    }
+
+    /// The first address of the network
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        addr.is_ipv4() == self.addr.is_ipv4() && IpNetwork::of(addr, self.prefix) == *self
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected an IP address or ADDR/PREFIX, got `{}`", s);
+
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().ok().filter(|p| *p <= bits).ok_or_else(invalid)?,
+            None => bits,
+        };
+
+        Ok(IpNetwork::of(addr, prefix))
+    }
+}
@@ -1,13 +1,16 @@
 use handle_errors::Error;
 use std::collections::HashMap;
+use utoipa::IntoParams;
 
 /// Pagination struct which is getting extracted
 /// from query params.
-#[derive(Default, Debug)]
+#[derive(Default, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Pagination {
-    /// The index of the last item which has to be returned
+    /// The number of items to return, given together with `offset`
     pub limit: Option<u32>,
-    /// The index of the first item which has to be returned
+    /// The number of items to skip, given together with `limit`
+    #[param(required = false)]
     pub offset: u32,
 }
 
@@ -18,11 +21,13 @@ pub struct Pagination {
 /// `/questions?start=1&end=10`
 /// # Example usage
 /// ```rust
+/// # use std::collections::HashMap;
+/// # use minimal_warp::types;
 /// let mut query = HashMap::new();
 /// query.insert("limit".to_string(), "1".to_string());
 /// query.insert("offset".to_string(), "10".to_string());
 /// let p = types::pagination::extract_pagination(query).unwrap();
-/// assert_eq!(p.limit, 1);
+/// assert_eq!(p.limit, Some(1));
 /// assert_eq!(p.offset, 10);
 /// ```
 pub fn extract_pagination(params: HashMap<String, String>) -> Result<Pagination, Error> {
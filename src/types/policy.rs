@@ -0,0 +1,69 @@
+use std::str::FromStr;
+
+/// What to do with a field in which the profanity API found offensive words
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Policy {
+    /// Store the text with offensive words replaced by `*`
+    #[default]
+    Censor,
+    /// Refuse the request with 422, listing the offensive words
+    Reject,
+    /// Store the text as typed and flag it for a moderator's review
+    Flag,
+}
+
+impl Policy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Policy::Censor => "censor",
+            Policy::Reject => "reject",
+            Policy::Flag => "flag",
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "censor" => Ok(Policy::Censor),
+            "reject" => Ok(Policy::Reject),
+            "flag" => Ok(Policy::Flag),
+            other => Err(format!("unknown policy `{}`, expected censor, reject or flag", other)),
+        }
+    }
+}
+
+/// The policy for one field of a route, given on the command line
+/// as `ROUTE.FIELD=POLICY`, or `ROUTE=POLICY` for all of its fields
+#[derive(Debug, Clone)]
+pub struct PolicyRule {
+    pub route: String,
+    pub field: Option<String>,
+    pub policy: Policy,
+}
+
+impl FromStr for PolicyRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, policy) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected ROUTE.FIELD=POLICY, got `{}`", s))?;
+        let (route, field) = match target.trim().split_once('.') {
+            Some((route, field)) => (route, Some(field.to_string())),
+            None => (target.trim(), None),
+        };
+
+        if route.is_empty() || field.as_deref() == Some("") {
+            return Err(format!("expected ROUTE.FIELD=POLICY, got `{}`", s));
+        }
+
+        Ok(PolicyRule {
+            route: route.to_string(),
+            field,
+            policy: policy.parse()?,
+        })
+    }
+}
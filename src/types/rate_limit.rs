@@ -0,0 +1,99 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Token bucket size and refill rate: `capacity` requests at once,
+/// refilled evenly over `per`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub capacity: u32,
+    pub per: Duration,
+}
+
+/// What's left of a client's limit after an allowed request,
+/// sent back as `RateLimit-*` headers
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset: u64,
+}
+
+impl Limit {
+    fn rate(&self) -> f64 {
+        self.capacity as f64 / self.per.as_secs_f64()
+    }
+
+    /// Refill a bucket which held `tokens` `elapsed` seconds ago and try to
+    /// take a token out of it. Returns the quota left, or the seconds to wait
+    /// until a token is available, together with the new token count.
+    pub fn take(&self, tokens: f64, elapsed: f64) -> (Result<Quota, u64>, f64) {
+        let capacity = self.capacity as f64;
+        let tokens = (tokens + elapsed.max(0.0) * self.rate()).min(capacity);
+
+        if tokens >= 1.0 {
+            let tokens = tokens - 1.0;
+            let quota = Quota {
+                limit: self.capacity,
+                remaining: tokens.floor() as u32,
+                reset: ((capacity - tokens) / self.rate()).ceil() as u64,
+            };
+            (Ok(quota), tokens)
+        } else {
+            let retry_after = ((1.0 - tokens) / self.rate()).ceil() as u64;
+            (Err(retry_after.max(1)), tokens)
+        }
+    }
+}
+
+/// Routes which can be rate limited
+pub const ROUTES: &[&str] = &[
+    "get_questions",
+    "get_question",
+    "add_question",
+    "update_question",
+    "delete_question",
+    "get_answer",
+    "add_answer",
+    "subscribe",
+];
+
+/// A limit for one route, given on the command line as `ROUTE=REQUESTS/SECONDS`
+#[derive(Debug, Clone)]
+pub struct RateLimitRule {
+    pub route: String,
+    pub limit: Limit,
+}
+
+impl FromStr for RateLimitRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected ROUTE=REQUESTS/SECONDS, got `{}`", s);
+
+        let (route, limit) = s.split_once('=').ok_or_else(invalid)?;
+        let (capacity, seconds) = limit.split_once('/').ok_or_else(invalid)?;
+        let capacity: u32 = capacity.trim().parse().map_err(|_| invalid())?;
+        let seconds: u64 = seconds.trim().parse().map_err(|_| invalid())?;
+
+        if route.trim().is_empty() || capacity == 0 || seconds == 0 {
+            return Err(invalid());
+        }
+        // A misspelt route would otherwise go unlimited without notice
+        if !ROUTES.contains(&route.trim()) {
+            return Err(format!(
+                "unknown route `{}`, expected one of {}",
+                route.trim(),
+                ROUTES.join(", ")
+            ));
+        }
+
+        Ok(RateLimitRule {
+            route: route.trim().to_string(),
+            limit: Limit {
+                capacity,
+                per: Duration::from_secs(seconds),
+            },
+        })
+    }
+}
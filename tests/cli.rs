@@ -0,0 +1,106 @@
+use std::process::Output;
+
+use clap::error::ErrorKind;
+
+use minimal_warp::cli::{Cli, Command, LogFormat, MigrateCommand};
+
+#[test]
+fn serves_by_default_with_top_level_flags() {
+    let cli = Cli::try_parse_args_from(["minimal-warp", "--listen", "0.0.0.0:8080"]).unwrap();
+
+    assert!(cli.command.is_none());
+    assert_eq!(cli.serve.listen, vec!["0.0.0.0:8080".parse().unwrap()]);
+}
+
+#[test]
+fn takes_serve_flags_after_the_subcommand() {
+    let cli =
+        Cli::try_parse_args_from(["minimal-warp", "serve", "--listen", "0.0.0.0:8080"]).unwrap();
+
+    match cli.command {
+        Some(Command::Serve(args)) => {
+            assert_eq!(args.listen, vec!["0.0.0.0:8080".parse().unwrap()])
+        }
+        command => panic!("parsed {:?}", command),
+    }
+}
+
+#[test]
+fn refuses_serve_flags_before_a_subcommand() {
+    for args in [
+        ["minimal-warp", "--listen", "0.0.0.0:8080", "serve"],
+        ["minimal-warp", "--no-migrate", "migrate", "up"],
+    ] {
+        let error = Cli::try_parse_args_from(args).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::ArgumentConflict, "{:?}", args);
+    }
+    let error =
+        Cli::try_parse_args_from(["minimal-warp", "--no-migrate", "migrate", "up"]).unwrap_err();
+    assert!(
+        error.to_string().contains("'--no-migrate' cannot be used before the subcommand 'migrate'"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn takes_global_flags_anywhere() {
+    for args in [
+        ["minimal-warp", "--log-format", "json", "migrate", "up"],
+        ["minimal-warp", "migrate", "up", "--log-format", "json"],
+    ] {
+        let cli = Cli::try_parse_args_from(args).unwrap();
+
+        assert!(matches!(cli.log.log_format, LogFormat::Json), "{:?}", args);
+        assert!(matches!(cli.command, Some(Command::Migrate(MigrateCommand::Up))));
+    }
+}
+
+#[test]
+fn refuses_rate_limits_for_unknown_routes() {
+    let error = Cli::try_parse_args_from(["minimal-warp", "--rate-limit", "add_questions=1/60"])
+        .unwrap_err();
+
+    assert_eq!(error.kind(), ErrorKind::ValueValidation);
+    assert!(error.to_string().contains("unknown route `add_questions`"), "{}", error);
+}
+
+#[test]
+fn refuses_body_limits_for_unknown_routes() {
+    let error = Cli::try_parse_args_from(["minimal-warp", "--body-limit", "get_questions=1KiB"])
+        .unwrap_err();
+
+    assert_eq!(error.kind(), ErrorKind::ValueValidation);
+    assert!(error.to_string().contains("unknown route `get_questions`"), "{}", error);
+}
+
+/// Run the binary against a database which hangs up on every connection.
+/// A refused connection would be retried for half a minute.
+fn run_without_database(args: &[&str]) -> Output {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            drop(stream);
+        }
+    });
+    std::process::Command::new(env!("CARGO_BIN_EXE_minimal-warp"))
+        .args(args)
+        .arg("--database-url")
+        .arg(format!("postgres://postgres@{}", addr))
+        .output()
+        .expect("Cannot run the binary")
+}
+
+#[test]
+fn reports_an_unreachable_database_without_panicking() {
+    for args in [&["serve", "--listen", "127.0.0.1:0"][..], &["migrate", "up"]] {
+        let output = run_without_database(args);
+
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("Error: Cannot connect to the database"), "{}", stderr);
+        assert!(!stderr.contains("panicked"), "{}", stderr);
+    }
+}
@@ -0,0 +1,223 @@
+mod support;
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, GzipEncoder};
+use serde_json::{json, Value};
+use tokio::io::AsyncReadExt;
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::test::request;
+
+use support::app::{TestApp, ADMIN_TOKEN};
+
+type Response = warp::http::Response<Bytes>;
+
+fn body(res: &Response) -> String {
+    String::from_utf8_lossy(res.body()).into_owned()
+}
+
+async fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    GzipEncoder::new(data).read_to_end(&mut compressed).await.unwrap();
+    compressed
+}
+
+async fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+    GzipDecoder::new(data).read_to_end(&mut decompressed).await.unwrap();
+    decompressed
+}
+
+async fn unbrotli(data: &[u8]) -> Vec<u8> {
+    let mut decompressed = Vec::new();
+    BrotliDecoder::new(data).read_to_end(&mut decompressed).await.unwrap();
+    decompressed
+}
+
+/// Enough questions for the list to be worth compressing
+async fn add_questions(app: &TestApp) {
+    for i in 0..20 {
+        let res = request()
+            .method("POST")
+            .path("/v1/questions")
+            .json(&json!({ "title": format!("Question {}", i), "content": "Some content" }))
+            .reply(&app.routes())
+            .await;
+        assert_eq!(res.status(), StatusCode::CREATED, "{}", body(&res));
+    }
+}
+
+async fn get_questions(app: &TestApp, accept_encoding: &str) -> Response {
+    request()
+        .path("/v1/questions")
+        .header("accept-encoding", accept_encoding)
+        .reply(&app.routes())
+        .await
+}
+
+#[tokio::test]
+async fn compresses_responses_by_accept_encoding() {
+    let app = TestApp::new().await;
+    add_questions(&app).await;
+    let plain = request().path("/v1/questions").reply(&app.routes()).await;
+
+    let brotli = get_questions(&app, "gzip, deflate, br").await;
+    let gzip = get_questions(&app, "br;q=0.5, gzip").await;
+    let identity = get_questions(&app, "deflate").await;
+
+    assert!(!plain.headers().contains_key("content-encoding"));
+    assert_eq!(brotli.headers()["content-encoding"], "br");
+    assert_eq!(unbrotli(brotli.body()).await, plain.body().as_ref());
+    assert_eq!(gzip.headers()["content-encoding"], "gzip");
+    assert_eq!(gunzip(gzip.body()).await, plain.body().as_ref());
+    assert!(gzip.body().len() < plain.body().len());
+    assert!(gzip.headers().get_all("vary").iter().any(|v| v == "accept-encoding"));
+    assert!(!identity.headers().contains_key("content-encoding"));
+    assert_eq!(identity.body(), plain.body());
+}
+
+#[tokio::test]
+async fn leaves_small_responses_uncompressed() {
+    let app = TestApp::new().await;
+
+    let res = get_questions(&app, "gzip").await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!res.headers().contains_key("content-encoding"));
+    assert_eq!(body(&res), "[]");
+}
+
+#[tokio::test]
+async fn compresses_streamed_exports() {
+    let app = TestApp::new().await;
+    add_questions(&app).await;
+
+    let res = request()
+        .path("/v1/admin/export")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .header("accept-encoding", "gzip")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    let export = String::from_utf8(gunzip(res.body()).await).unwrap();
+    assert_eq!(export.lines().count(), 20);
+}
+
+#[tokio::test]
+async fn accepts_gzip_request_bodies() {
+    let app = TestApp::new().await;
+    let question = json!({ "title": "Zipped", "content": "Damn" });
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("content-type", "application/json")
+        .header("content-encoding", "gzip")
+        .body(gzip(question.to_string().as_bytes()).await)
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::CREATED, "{}", body(&res));
+    let question: Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(question["content"], "****");
+}
+
+#[tokio::test]
+async fn rejects_corrupt_gzip_request_bodies() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("content-encoding", "gzip")
+        .body("{\"title\": \"Not zipped\"}")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body(&res).starts_with("Request body deserialize error"), "{}", body(&res));
+}
+
+#[tokio::test]
+async fn rejects_unsupported_content_encoding() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("content-encoding", "zstd")
+        .body("{}")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(res.headers()["accept-encoding"], "gzip, identity");
+    assert_eq!(app.api.hits(), 0);
+}
+
+#[tokio::test]
+async fn rejects_bodies_over_the_route_limit() {
+    let mut app = TestApp::new().await;
+    app.args.body_limits = vec!["add_question=100".parse().unwrap()];
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": "Long", "content": "x".repeat(100) }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(
+        body(&res).starts_with("Request body exceeds the limit of 100 bytes"),
+        "{}",
+        body(&res)
+    );
+    assert_eq!(app.api.hits(), 0);
+
+    let res = request()
+        .method("POST")
+        .path("/v1/answers")
+        .json(&json!({ "content": "x".repeat(100), "question_id": 1 }))
+        .reply(&app.routes())
+        .await;
+    assert_ne!(res.status(), StatusCode::PAYLOAD_TOO_LARGE, "other routes keep their limit");
+}
+
+#[tokio::test]
+async fn limits_decompressed_size_of_bodies() {
+    let mut app = TestApp::new().await;
+    app.args.body_limits = vec!["add_question=1KiB".parse().unwrap()];
+    let padded = format!("{{\"title\": \"Bomb\", \"content\": \"{}\"}}", " ".repeat(100_000));
+    let compressed = gzip(padded.as_bytes()).await;
+    assert!(compressed.len() < 1024);
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("content-encoding", "gzip")
+        .body(compressed)
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE, "{}", body(&res));
+}
+
+#[tokio::test]
+async fn rejects_imports_over_the_limit() {
+    let mut app = TestApp::new().await;
+    app.args.body_limits = vec!["import=1KiB".parse().unwrap()];
+    let line = json!({ "title": "Imported", "content": "Content", "answers": [] });
+    let ndjson: String = (0..20).map(|_| format!("{}\n", line)).collect();
+
+    let res = request()
+        .method("POST")
+        .path("/v1/admin/import")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .body(ndjson)
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE, "{}", body(&res));
+}
@@ -0,0 +1,209 @@
+mod support;
+
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::test::{request, RequestBuilder};
+
+use minimal_warp::cors::Cors;
+use minimal_warp::rate_limit::ClientAddr;
+use minimal_warp::types::cors::CorsOrigin;
+use support::app::TestApp;
+
+type Response = warp::http::Response<Bytes>;
+
+fn body(res: &Response) -> String {
+    String::from_utf8_lossy(res.body()).into_owned()
+}
+
+fn allow_origins(app: &mut TestApp, origins: &[&str]) {
+    app.args.cors_origins = origins.iter().map(|origin| origin.parse().unwrap()).collect();
+}
+
+fn preflight(origin: &str, method: &str) -> RequestBuilder {
+    request()
+        .method("OPTIONS")
+        .path("/v1/questions")
+        .header("origin", origin)
+        .header("access-control-request-method", method)
+}
+
+async fn get_from(app: &TestApp, origin: &str) -> Response {
+    request()
+        .path("/v1/questions")
+        .header("host", "api.example.com")
+        .header("origin", origin)
+        .reply(&app.routes())
+        .await
+}
+
+#[tokio::test]
+async fn allows_listed_origins_only() {
+    let mut app = TestApp::new().await;
+    allow_origins(&mut app, &["https://app.example.com", "http://localhost:8080"]);
+
+    let allowed = get_from(&app, "http://localhost:8080").await;
+    let forbidden = get_from(&app, "https://evil.example.org").await;
+    let without_origin = request().path("/v1/questions").reply(&app.routes()).await;
+
+    assert_eq!(allowed.status(), StatusCode::OK);
+    assert_eq!(allowed.headers()["access-control-allow-origin"], "http://localhost:8080");
+    assert!(allowed.headers()["access-control-expose-headers"]
+        .to_str()
+        .unwrap()
+        .contains("x-request-id"));
+    assert!(allowed.headers().get_all("vary").iter().any(|v| v == "origin"));
+    assert!(!allowed.headers().contains_key("access-control-allow-credentials"));
+
+    assert_eq!(forbidden.status(), StatusCode::FORBIDDEN);
+    assert!(!forbidden.headers().contains_key("access-control-allow-origin"));
+    assert!(
+        body(&forbidden).starts_with(
+            "CORS request from `https://evil.example.org` forbidden: origin not allowed"
+        ),
+        "{}",
+        body(&forbidden)
+    );
+
+    assert_eq!(without_origin.status(), StatusCode::OK);
+    assert!(!without_origin.headers().contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn allows_subdomains_of_wildcard_origins() {
+    let mut app = TestApp::new().await;
+    allow_origins(&mut app, &["https://*.example.com"]);
+
+    for origin in [
+        "https://app.example.com",
+        "https://a.b.example.com",
+        "https://APP.example.com",
+    ] {
+        assert_eq!(get_from(&app, origin).await.status(), StatusCode::OK, "{}", origin);
+    }
+    for origin in [
+        "https://example.com",
+        "http://app.example.com",
+        "https://evilexample.com",
+        "https://app.example.com:8443",
+        "https://app.example.com.evil.org",
+    ] {
+        assert_eq!(get_from(&app, origin).await.status(), StatusCode::FORBIDDEN, "{}", origin);
+    }
+}
+
+#[tokio::test]
+async fn allows_requests_from_its_own_origin() {
+    let mut app = TestApp::new().await;
+    allow_origins(&mut app, &["https://app.example.com"]);
+
+    let res = get_from(&app, "http://api.example.com").await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!res.headers().contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn tells_its_own_origin_by_scheme() {
+    let mut app = TestApp::new().await;
+    allow_origins(&mut app, &["https://app.example.com"]);
+    app.args.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+    let from = |client: [u8; 4]| {
+        request()
+            .path("/v1/questions")
+            .header("host", "api.example.com")
+            .header("origin", "https://api.example.com")
+            .header("x-forwarded-proto", "https")
+            .extension(ClientAddr((client, 4711).into()))
+    };
+
+    let other_scheme = get_from(&app, "https://api.example.com").await;
+    let untrusted = from([192, 0, 2, 1]).reply(&app.routes()).await;
+    let proxied = from([10, 0, 0, 1]).reply(&app.routes()).await;
+
+    assert_eq!(other_scheme.status(), StatusCode::FORBIDDEN);
+    assert_eq!(untrusted.status(), StatusCode::FORBIDDEN);
+    assert_eq!(proxied.status(), StatusCode::OK, "{}", body(&proxied));
+}
+
+#[tokio::test]
+async fn answers_preflight_with_configured_policy() {
+    let mut app = TestApp::new().await;
+    allow_origins(&mut app, &["https://app.example.com"]);
+    app.args.cors_methods = vec!["GET".parse().unwrap(), "PATCH".parse().unwrap()];
+    app.args.cors_headers = vec!["content-type".parse().unwrap(), "x-custom".parse().unwrap()];
+    app.args.cors_allow_credentials = true;
+    app.args.cors_max_age = 3600;
+
+    let res = preflight("https://app.example.com", "PATCH")
+        .header("access-control-request-headers", "X-Custom, content-type")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["access-control-allow-origin"], "https://app.example.com");
+    assert_eq!(res.headers()["access-control-allow-methods"], "GET, PATCH");
+    assert_eq!(res.headers()["access-control-allow-headers"], "content-type, x-custom");
+    assert_eq!(res.headers()["access-control-allow-credentials"], "true");
+    assert_eq!(res.headers()["access-control-max-age"], "3600");
+
+    let res = preflight("https://app.example.com", "PUT").reply(&app.routes()).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = preflight("https://other.example.com", "GET").reply(&app.routes()).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert!(
+        body(&res).starts_with(
+            "CORS request from `https://other.example.com` forbidden: origin not allowed"
+        ),
+        "{}",
+        body(&res)
+    );
+}
+
+#[tokio::test]
+async fn lets_allowed_origins_read_errors() {
+    let mut app = TestApp::new().await;
+    allow_origins(&mut app, &["https://app.example.com"]);
+
+    let res = request()
+        .path("/v1/questions/not-a-number")
+        .header("origin", "https://app.example.com")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(res.headers()["access-control-allow-origin"], "https://app.example.com");
+}
+
+#[tokio::test]
+async fn refuses_credentials_for_any_origin() {
+    let mut app = TestApp::new().await;
+    app.args.cors_allow_credentials = true;
+
+    assert!(Cors::new(&app.args).is_err());
+
+    allow_origins(&mut app, &["https://*.example.com"]);
+    assert!(Cors::new(&app.args).is_ok());
+}
+
+#[test]
+fn parses_origins() {
+    assert_eq!("*".parse::<CorsOrigin>(), Ok(CorsOrigin::Any));
+    assert_eq!(
+        "HTTPS://App.example.com:8443".parse::<CorsOrigin>(),
+        Ok(CorsOrigin::Exact("https://app.example.com:8443".to_string()))
+    );
+    assert!(matches!(
+        "https://*.example.com".parse::<CorsOrigin>(),
+        Ok(CorsOrigin::Subdomains { .. })
+    ));
+    for invalid in [
+        "example.com",
+        "https://example.com/",
+        "https://*example.com",
+        "https://app.*.example.com",
+        "https://",
+    ] {
+        assert!(invalid.parse::<CorsOrigin>().is_err(), "{}", invalid);
+    }
+}
@@ -0,0 +1,261 @@
+mod support;
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio_util::sync::CancellationToken;
+use warp::http::StatusCode;
+use warp::test::{request, WsClient};
+
+use minimal_warp::cli::ModerationMode;
+use minimal_warp::events::{self, Events};
+use minimal_warp::moderation;
+use minimal_warp::store::Store;
+use minimal_warp::types::event::Event;
+use minimal_warp::types::question::NewQuestion;
+use support::app::TestApp;
+
+async fn connect(app: &TestApp) -> WsClient {
+    warp::test::ws().path("/v1/ws").handshake(app.routes()).await.unwrap()
+}
+
+async fn recv(client: &mut WsClient) -> Value {
+    let message = tokio::time::timeout(Duration::from_secs(10), client.recv())
+        .await
+        .expect("no message within 10s")
+        .unwrap();
+    serde_json::from_str(message.to_str().unwrap()).unwrap()
+}
+
+/// Nothing arrives for a while
+async fn assert_silent(client: &mut WsClient) {
+    let message = tokio::time::timeout(Duration::from_millis(200), client.recv()).await;
+    assert!(message.is_err(), "unexpected message {:?}", message);
+}
+
+async fn subscribe(client: &mut WsClient, topic: Value) {
+    client.send_text(json!({ "type": "subscribe", "topic": topic }).to_string()).await;
+    assert_eq!(recv(client).await, json!({ "type": "subscribed", "topic": topic }));
+}
+
+async fn add_question(app: &TestApp, title: &str, tags: &[&str]) -> Value {
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": title, "content": "Content", "tags": tags }))
+        .reply(&app.routes())
+        .await;
+    serde_json::from_slice(res.body()).unwrap()
+}
+
+#[tokio::test]
+async fn pushes_events_of_subscribed_tags() {
+    let app = TestApp::new().await;
+    let mut client = connect(&app).await;
+    subscribe(&mut client, json!({ "tag": "rust" })).await;
+
+    add_question(&app, "About Go", &["go"]).await;
+    let question = add_question(&app, "About Rust", &["rust", "warp"]).await;
+
+    let event = recv(&mut client).await;
+    assert_eq!(event, json!({ "type": "question_created", "question": question }));
+}
+
+#[tokio::test]
+async fn pushes_changes_of_subscribed_questions() {
+    let app = TestApp::new().await;
+    let question = add_question(&app, "Title", &[]).await;
+    let id = question["id"].as_i64().unwrap();
+    let mut client = connect(&app).await;
+    subscribe(&mut client, json!({ "question": id })).await;
+
+    request()
+        .method("POST")
+        .path("/v1/answers")
+        .json(&json!({ "content": "Damn good", "question_id": id }))
+        .reply(&app.routes())
+        .await;
+    let event = recv(&mut client).await;
+    assert_eq!(event["type"], "answer_created");
+    assert_eq!(event["answer"]["content"], "**** good");
+    assert_eq!(event["answer"]["question_id"], id);
+
+    request()
+        .method("PUT")
+        .path(&format!("/v1/questions/{}", id))
+        .json(&json!({ "id": id, "title": "New title", "content": "Content" }))
+        .reply(&app.routes())
+        .await;
+    let event = recv(&mut client).await;
+    assert_eq!(event["type"], "question_updated");
+    assert_eq!(event["question"]["title"], "New title");
+
+    add_question(&app, "Another", &[]).await;
+    assert_silent(&mut client).await;
+}
+
+#[tokio::test]
+async fn pushes_deletions() {
+    let app = TestApp::new().await;
+    let question = add_question(&app, "Title", &["rust"]).await;
+    let id = question["id"].as_i64().unwrap();
+    let mut client = connect(&app).await;
+    subscribe(&mut client, json!({ "tag": "rust" })).await;
+
+    request().method("DELETE").path(&format!("/v1/questions/{}", id)).reply(&app.routes()).await;
+
+    let event = recv(&mut client).await;
+    assert_eq!(
+        event,
+        json!({ "type": "question_deleted", "question_id": id, "tags": ["rust"] })
+    );
+}
+
+#[tokio::test]
+async fn keeps_rejected_questions_quiet() {
+    let app = TestApp::new().await;
+    let question = add_question(&app, "Rejected", &["rust"]).await;
+    let id = question["id"].as_i64().unwrap();
+    sqlx::query("UPDATE questions SET status = 'rejected' WHERE id = $1")
+        .bind(id as i32)
+        .execute(&app.db.store.connection)
+        .await
+        .unwrap();
+    let mut client = connect(&app).await;
+    subscribe(&mut client, json!("all")).await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/answers")
+        .json(&json!({ "content": "Answer", "question_id": id }))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::CREATED);
+    assert_silent(&mut client).await;
+
+    request().method("DELETE").path(&format!("/v1/questions/{}", id)).reply(&app.routes()).await;
+    assert_silent(&mut client).await;
+}
+
+#[tokio::test]
+async fn stops_pushing_after_unsubscribing() {
+    let app = TestApp::new().await;
+    let mut client = connect(&app).await;
+    subscribe(&mut client, json!("all")).await;
+
+    add_question(&app, "First", &[]).await;
+    assert_eq!(recv(&mut client).await["question"]["title"], "First");
+
+    client.send_text(json!({ "type": "unsubscribe", "topic": "all" }).to_string()).await;
+    assert_eq!(recv(&mut client).await, json!({ "type": "unsubscribed", "topic": "all" }));
+    add_question(&app, "Second", &[]).await;
+    assert_silent(&mut client).await;
+}
+
+#[tokio::test]
+async fn answers_invalid_messages_with_errors() {
+    let app = TestApp::new().await;
+    let mut client = connect(&app).await;
+
+    client.send_text(r#"{"type": "subscribe", "topic": "everything"}"#).await;
+    let reply = recv(&mut client).await;
+
+    assert_eq!(reply["type"], "error");
+    assert!(reply["message"].as_str().unwrap().starts_with("Cannot read message"), "{}", reply);
+    subscribe(&mut client, json!("all")).await;
+}
+
+#[tokio::test]
+async fn publishes_moderated_content_once_approved() {
+    let mut app = TestApp::new().await;
+    app.args.moderation = ModerationMode::Async;
+    let mut client = connect(&app).await;
+    subscribe(&mut client, json!("all")).await;
+
+    let question = add_question(&app, "Queued", &[]).await;
+    assert_silent(&mut client).await;
+
+    let workers = moderation::spawn_workers(
+        1,
+        app.db.store.clone(),
+        app.profanity.clone(),
+        app.readiness.clone(),
+        5,
+    );
+    let event = recv(&mut client).await;
+    assert_eq!(event["type"], "question_created");
+    assert_eq!(event["question"]["id"], question["id"]);
+
+    app.readiness.shutting_down();
+    futures::future::join_all(workers).await;
+}
+
+#[tokio::test]
+async fn closes_connections_on_shutdown() {
+    let app = TestApp::new().await;
+    let mut client = connect(&app).await;
+
+    app.readiness.shutting_down();
+
+    client.recv_closed().await.unwrap();
+}
+
+#[tokio::test]
+async fn requires_a_websocket_upgrade() {
+    let app = TestApp::new().await;
+
+    let res = request().path("/v1/ws").reply(&app.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+    assert_eq!(res.headers()["upgrade"], "websocket");
+}
+
+#[tokio::test]
+async fn shares_events_with_other_instances() {
+    let app = TestApp::new().await;
+    let mut ours = app.db.store.clone();
+    ours.events.share();
+    // Another instance, on the same database
+    let theirs = Store {
+        connection: ours.connection.clone(),
+        events: Events::default(),
+    };
+    let stop = CancellationToken::new();
+    events::listen(ours.clone(), stop.clone());
+    events::listen(theirs.clone(), stop.clone());
+    let mut our_events = ours.events.subscribe();
+    let mut their_events = theirs.events.subscribe();
+    // Give the listeners time to start listening
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let question = ours
+        .clone()
+        .add_question(
+            NewQuestion {
+                title: "Shared".to_string(),
+                content: "Content".to_string(),
+                tags: Some(vec!["rust".to_string()]),
+            },
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let received = tokio::time::timeout(Duration::from_secs(10), their_events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    match received {
+        Event::QuestionCreated { question: shared } => {
+            assert_eq!(shared.id, question.id);
+            assert_eq!(shared.tags, question.tags);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    assert!(matches!(our_events.recv().await.unwrap(), Event::QuestionCreated { .. }));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(our_events.try_recv().is_err(), "our own notification came back");
+    stop.cancel();
+}
@@ -0,0 +1,147 @@
+mod support;
+
+use std::path::Path;
+use std::process::Output;
+
+use clap::Parser;
+
+use minimal_warp::cli::{Cli, Command, MigrateCommand};
+use minimal_warp::migrate::{self, MIGRATOR};
+use support::db::TestDb;
+
+/// Versions of the up migrations, oldest first
+fn versions() -> Vec<i64> {
+    MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| m.version)
+        .collect()
+}
+
+/// Run the binary with the given arguments
+fn run(args: &[&str]) -> Output {
+    std::process::Command::new(env!("CARGO_BIN_EXE_minimal-warp"))
+        .args(args)
+        .output()
+        .expect("Cannot run the binary")
+}
+
+async fn table_exists(db: &TestDb, table: &str) -> bool {
+    sqlx::query("SELECT to_regclass($1) IS NOT NULL")
+        .bind(table)
+        .fetch_one(&db.store.connection)
+        .await
+        .map(|row| sqlx::Row::get(&row, 0))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn applies_all_migrations() {
+    let db = TestDb::new().await;
+
+    assert_eq!(migrate::pending(&db.store).await.unwrap(), 0);
+    assert!(table_exists(&db, "questions").await);
+    // Running again with nothing pending is fine
+    migrate::run(&db.store).await.unwrap();
+}
+
+#[tokio::test]
+async fn reverts_the_latest_migration_by_default() {
+    let db = TestDb::new().await;
+
+    migrate::revert(&db.store, None).await.unwrap();
+    assert_eq!(migrate::pending(&db.store).await.unwrap(), 1);
+
+    migrate::revert(&db.store, None).await.unwrap();
+    assert_eq!(migrate::pending(&db.store).await.unwrap(), 2);
+
+    migrate::run(&db.store).await.unwrap();
+    assert_eq!(migrate::pending(&db.store).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn reverts_migrations_newer_than_a_version() {
+    let db = TestDb::new().await;
+    let versions = versions();
+
+    migrate::revert(&db.store, Some(versions[0])).await.unwrap();
+
+    assert_eq!(migrate::pending(&db.store).await.unwrap(), versions.len() - 1);
+    assert!(table_exists(&db, "questions").await);
+    assert!(!table_exists(&db, "answers").await);
+
+    migrate::revert(&db.store, Some(0)).await.unwrap();
+    assert_eq!(migrate::pending(&db.store).await.unwrap(), versions.len());
+    assert!(!table_exists(&db, "questions").await);
+}
+
+#[test]
+fn parses_migrate_subcommands() {
+    let parse = |args: &[&str]| {
+        let args = ["minimal-warp"].iter().chain(args);
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Migrate(command)) => command,
+            command => panic!("not a migrate command: {:?}", command),
+        }
+    };
+
+    assert!(matches!(parse(&["migrate", "up"]), MigrateCommand::Up));
+    assert!(matches!(parse(&["migrate", "status"]), MigrateCommand::Status));
+    assert!(matches!(parse(&["migrate", "down"]), MigrateCommand::Down { to: None }));
+    assert!(matches!(
+        parse(&["migrate", "down", "--to", "20220608031207"]),
+        MigrateCommand::Down { to: Some(20220608031207) }
+    ));
+    match parse(&["migrate", "new", "users_table"]) {
+        MigrateCommand::New { name, source } => {
+            assert_eq!(name, "users_table");
+            assert_eq!(source, std::path::PathBuf::from("migrations"));
+        }
+        command => panic!("unexpected command {:?}", command),
+    }
+    assert!(Cli::try_parse_from(["minimal-warp", "migrate", "sideways"]).is_err());
+}
+
+#[test]
+fn creates_migration_file_pairs() {
+    let dir = std::env::temp_dir().join(format!("minimal-warp-migrate-{}", std::process::id()));
+
+    let output = run(&["migrate", "new", " users table ", "--source", dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{:?}", output);
+
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names.len(), 2, "{:?}", names);
+
+    let (down, up) = (&names[0], &names[1]);
+    assert!(up.ends_with("_users_table.up.sql"), "{}", up);
+    assert_eq!(up.replace(".up.", ".down."), *down);
+    assert!(up[..14].chars().all(|c| c.is_ascii_digit()), "{}", up);
+    assert!(std::fs::read_to_string(Path::new(&dir).join(up)).unwrap().starts_with("--"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_invalid_migrate_arguments() {
+    assert!(!run(&["migrate", "sideways"]).status.success());
+    assert!(!run(&["migrate", "down", "--to", "yesterday"]).status.success());
+    assert!(!run(&["migrate", "new"]).status.success());
+}
+
+#[test]
+fn reports_failures_without_panicking() {
+    // A file where the migrations folder should be
+    let file = std::env::temp_dir().join(format!("minimal-warp-not-a-dir-{}", std::process::id()));
+    std::fs::write(&file, "").unwrap();
+
+    let output = run(&["migrate", "new", "users_table", "--source", file.to_str().unwrap()]);
+    std::fs::remove_file(&file).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Error: Cannot create migration files"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
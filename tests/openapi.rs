@@ -0,0 +1,170 @@
+mod support;
+
+use serde_json::Value;
+use warp::http::StatusCode;
+use warp::test::request;
+
+use support::app::TestApp;
+
+const METHODS: &[&str] = &["GET", "POST", "PUT", "DELETE"];
+
+async fn spec(app: &TestApp) -> Value {
+    let res = request().path("/openapi.json").reply(&app.routes()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    serde_json::from_slice(res.body()).unwrap()
+}
+
+/// Documented paths with their methods, path parameters filled in with `1`
+fn operations(spec: &Value) -> Vec<(String, Vec<String>)> {
+    spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(path, item)| {
+            let path = path
+                .split('/')
+                .map(|segment| if segment.starts_with('{') { "1" } else { segment })
+                .collect::<Vec<_>>()
+                .join("/");
+            let methods = item
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(|method| method.to_uppercase())
+                .collect();
+            (path, methods)
+        })
+        .collect()
+}
+
+fn is_not_found(res: &warp::http::Response<warp::hyper::body::Bytes>) -> bool {
+    res.status() == StatusCode::NOT_FOUND && res.body().as_ref() == b"Route not found"
+}
+
+#[tokio::test]
+async fn documents_openapi_3_1() {
+    let app = TestApp::new().await;
+
+    let spec = spec(&app).await;
+
+    assert_eq!(spec["openapi"], "3.1.0");
+    for schema in ["Question", "NewQuestion", "NewAnswer", "QuestionId"] {
+        assert!(spec["components"]["schemas"][schema].is_object(), "{} is missing", schema);
+    }
+    let parameters = &spec["paths"]["/v1/questions"]["get"]["parameters"];
+    assert_eq!(parameters[0]["name"], "limit");
+    assert_eq!(parameters[1]["name"], "offset");
+}
+
+#[tokio::test]
+async fn documents_unversioned_paths_as_deprecated() {
+    let app = TestApp::new().await;
+
+    let spec = spec(&app).await;
+
+    let v1 = &spec["paths"]["/v1/questions/{id}"]["put"];
+    let legacy = &spec["paths"]["/questions/{id}"]["put"];
+    assert_eq!(v1["operationId"], "update_question");
+    assert!(v1.get("deprecated").is_none());
+    assert_eq!(legacy["operationId"], "update_question_unversioned");
+    assert_eq!(legacy["deprecated"], true);
+    assert_eq!(legacy["responses"], v1["responses"]);
+}
+
+#[tokio::test]
+async fn references_resolve() {
+    fn check(spec: &Value, value: &Value) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(reference)) = map.get("$ref") {
+                    let pointer = reference.trim_start_matches('#');
+                    assert!(spec.pointer(pointer).is_some(), "{} does not resolve", reference);
+                }
+                map.values().for_each(|value| check(spec, value));
+            }
+            Value::Array(values) => values.iter().for_each(|value| check(spec, value)),
+            _ => {}
+        }
+    }
+
+    let app = TestApp::new().await;
+    let spec = spec(&app).await;
+
+    check(&spec, &spec);
+}
+
+#[tokio::test]
+async fn documented_operations_are_routed() {
+    let app = TestApp::new().await;
+    let spec = spec(&app).await;
+
+    for (path, methods) in operations(&spec) {
+        for method in methods {
+            let res = request().method(&method).path(&path).reply(&app.routes()).await;
+
+            assert!(!is_not_found(&res), "{} {} is documented but not routed", method, path);
+        }
+    }
+}
+
+#[tokio::test]
+async fn undocumented_methods_are_not_routed() {
+    let app = TestApp::new().await;
+    let spec = spec(&app).await;
+
+    for (path, methods) in operations(&spec) {
+        for method in METHODS.iter().filter(|m| !methods.contains(&m.to_string())) {
+            let res = request().method(method).path(&path).reply(&app.routes()).await;
+
+            assert!(is_not_found(&res), "{} {} is routed but not documented", method, path);
+        }
+    }
+}
+
+/// Catches routes added to the filter tree under a new path without documenting them
+#[tokio::test]
+async fn routed_paths_are_documented() {
+    let app = TestApp::new().await;
+    let spec = spec(&app).await;
+    let documented: Vec<&str> = spec["paths"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .flat_map(|path| path.split('/'))
+        .collect();
+
+    let sources = [include_str!("../src/routes/mod.rs"), include_str!("../src/routes/v1.rs")];
+    for segment in sources.iter().flat_map(|source| source.split("warp::path(\"").skip(1)) {
+        let segment = &segment[..segment.find('"').unwrap()];
+        // The Swagger UI is a page rather than part of the API
+        if segment == "docs" {
+            continue;
+        }
+
+        assert!(
+            documented.contains(&segment),
+            "/{} is routed but not documented",
+            segment
+        );
+    }
+}
+
+#[tokio::test]
+async fn serves_swagger_ui() {
+    let app = TestApp::new().await;
+
+    let res = request().path("/docs").reply(&app.routes()).await;
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(res.headers()["location"], "/docs/");
+
+    let res = request().path("/docs/").reply(&app.routes()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+
+    let res = request().path("/docs/swagger-initializer.js").reply(&app.routes()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(String::from_utf8_lossy(res.body()).contains("\"url\": \"/openapi.json\""));
+
+    let res = request().path("/docs/unknown.js").reply(&app.routes()).await;
+    assert!(is_not_found(&res));
+}
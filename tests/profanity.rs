@@ -0,0 +1,336 @@
+mod support;
+
+use std::time::Duration;
+
+use handle_errors::Error;
+use minimal_warp::cli::{DegradedMode, ProfanityArgs};
+use minimal_warp::profanity::Profanity;
+use minimal_warp::types::policy::{Policy, PolicyRule};
+
+use support::apilayer::{profanity_args, Behavior, MockApiLayer, API_KEY};
+
+fn client(mock: &MockApiLayer) -> Profanity {
+    Profanity::new(&profanity_args(mock.url()))
+}
+
+#[tokio::test]
+async fn censors_offensive_words() {
+    let mock = MockApiLayer::start(Behavior::Censor);
+
+    let detection = client(&mock).detect("Damn, it works".to_string()).await.unwrap();
+
+    assert_eq!(detection.original, "Damn, it works");
+    assert_eq!(detection.censored, "****, it works");
+    assert_eq!(detection.bad_words.len(), 1);
+    assert_eq!(detection.bad_words[0].original, "Damn");
+    assert_eq!(detection.bad_words[0].replaced_len, 4);
+    assert!(!detection.unchecked);
+
+    let received = mock.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].query, "censor_character=*");
+    assert_eq!(received[0].api_key.as_deref(), Some(API_KEY));
+    assert_eq!(received[0].body, "Damn, it works");
+    assert_eq!(received[0].request_id, None);
+}
+
+#[tokio::test]
+async fn accepts_base_url_with_trailing_slash() {
+    let mock = MockApiLayer::start(Behavior::Censor);
+    let profanity = Profanity::new(&profanity_args(format!("{}/", mock.url())));
+
+    profanity.detect("fine".to_string()).await.unwrap();
+
+    assert_eq!(mock.hits(), 1);
+}
+
+#[tokio::test]
+async fn forwards_the_request_id() {
+    let mock = MockApiLayer::start(Behavior::Censor);
+    let profanity = client(&mock);
+
+    handle_errors::REQUEST_ID
+        .scope("req-1".to_string(), profanity.detect("fine".to_string()))
+        .await
+        .unwrap();
+
+    assert_eq!(mock.received()[0].request_id.as_deref(), Some("req-1"));
+}
+
+#[tokio::test]
+async fn client_error_carries_the_api_message() {
+    let mock = MockApiLayer::start(Behavior::Error(401, "Invalid authentication credentials"));
+
+    match client(&mock).detect("fine".to_string()).await {
+        Err(Error::ClientError(e)) => {
+            assert_eq!(e.status, 401);
+            assert_eq!(e.message, "Invalid authentication credentials");
+        }
+        other => panic!("expected a client error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn server_error_carries_the_api_message() {
+    let mock = MockApiLayer::start(Behavior::Error(500, "Something went wrong"));
+
+    match client(&mock).detect("fine".to_string()).await {
+        Err(Error::ServerError(e)) => {
+            assert_eq!(e.status, 500);
+            assert_eq!(e.message, "Something went wrong");
+        }
+        other => panic!("expected a server error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn error_without_api_response_keeps_the_raw_body() {
+    let mock = MockApiLayer::start(Behavior::RawError(502, "<html>Bad Gateway</html>"));
+
+    match client(&mock).detect("fine".to_string()).await {
+        Err(Error::ServerError(e)) => {
+            assert_eq!(e.status, 502);
+            assert_eq!(e.message, "<html>Bad Gateway</html>");
+        }
+        other => panic!("expected a server error, got {:?}", other),
+    }
+
+    mock.set_behavior(Behavior::RawError(429, ""));
+    match client(&mock).detect("fine".to_string()).await {
+        Err(Error::ClientError(e)) => {
+            assert_eq!(e.status, 429);
+            assert_eq!(e.message, "");
+        }
+        other => panic!("expected a client error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn malformed_response_is_an_api_error() {
+    let mock = MockApiLayer::start(Behavior::Malformed);
+
+    match client(&mock).detect("fine".to_string()).await {
+        Err(Error::ReqwestAPIError(e)) => assert!(e.is_decode()),
+        other => panic!("expected a decoding error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn slow_response_times_out() {
+    let mock = MockApiLayer::start(Behavior::Slow(Duration::from_secs(2)));
+
+    match client(&mock).detect("fine".to_string()).await {
+        Err(Error::MiddlewareReqwestAPIError(e)) => {
+            assert!(e.to_string().contains("timed out"), "{}", e)
+        }
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn slow_response_within_the_timeout_succeeds() {
+    let mock = MockApiLayer::start(Behavior::Slow(Duration::from_millis(100)));
+
+    let detection = client(&mock).detect("shit".to_string()).await.unwrap();
+
+    assert_eq!(detection.censored, "****");
+}
+
+#[tokio::test]
+async fn unreachable_api_is_a_middleware_error() {
+    // Bind and release a port so nothing is listening on it
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let profanity = Profanity::new(&profanity_args(format!("http://{}", addr)));
+
+    match profanity.detect("fine".to_string()).await {
+        Err(Error::MiddlewareReqwestAPIError(_)) => {}
+        other => panic!("expected a connection error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let mock = MockApiLayer::start(Behavior::Error(503, "Try again"));
+    let profanity = Profanity::new(&ProfanityArgs {
+        profanity_retries: 1,
+        ..profanity_args(mock.url())
+    });
+
+    assert!(matches!(
+        profanity.detect("fine".to_string()).await,
+        Err(Error::ServerError(_))
+    ));
+    assert_eq!(mock.hits(), 2);
+}
+
+#[tokio::test]
+async fn does_not_retry_client_errors() {
+    let mock = MockApiLayer::start(Behavior::Error(400, "Bad request"));
+    let profanity = Profanity::new(&ProfanityArgs {
+        profanity_retries: 1,
+        ..profanity_args(mock.url())
+    });
+
+    assert!(matches!(
+        profanity.detect("fine".to_string()).await,
+        Err(Error::ClientError(_))
+    ));
+    assert_eq!(mock.hits(), 1);
+}
+
+#[tokio::test]
+async fn caches_successful_results_only() {
+    let mock = MockApiLayer::start(Behavior::Error(500, "Down"));
+    let profanity = client(&mock);
+
+    assert!(profanity.detect("shit".to_string()).await.is_err());
+    mock.set_behavior(Behavior::Censor);
+    assert_eq!(profanity.detect("shit".to_string()).await.unwrap().censored, "****");
+    assert_eq!(profanity.detect("shit".to_string()).await.unwrap().censored, "****");
+
+    assert_eq!(mock.hits(), 2);
+}
+
+#[tokio::test]
+async fn disabled_cache_calls_the_api_every_time() {
+    let mock = MockApiLayer::start(Behavior::Censor);
+    let profanity = Profanity::new(&ProfanityArgs {
+        profanity_cache_size: 0,
+        ..profanity_args(mock.url())
+    });
+
+    profanity.detect("fine".to_string()).await.unwrap();
+    profanity.detect("fine".to_string()).await.unwrap();
+
+    assert_eq!(mock.hits(), 2);
+}
+
+/// A client whose breaker opens after two failed calls, which the mock answers with 500
+async fn open_breaker(mock: &MockApiLayer, mode: DegradedMode) -> Profanity {
+    let profanity = Profanity::new(&ProfanityArgs {
+        profanity_failure_threshold: 2,
+        profanity_degraded_mode: mode,
+        ..profanity_args(mock.url())
+    });
+    for _ in 0..2 {
+        assert!(profanity.detect("fine".to_string()).await.is_err());
+    }
+    mock.set_behavior(Behavior::Censor);
+    profanity
+}
+
+#[tokio::test]
+async fn open_breaker_rejects_in_reject_mode() {
+    let mock = MockApiLayer::start(Behavior::Error(500, "Down"));
+    let profanity = open_breaker(&mock, DegradedMode::Reject).await;
+
+    match profanity.detect("shit".to_string()).await {
+        Err(Error::ServiceUnavailable(retry_after)) => assert!(retry_after <= 30),
+        other => panic!("expected the service to be unavailable, got {:?}", other),
+    }
+    assert_eq!(mock.hits(), 2);
+}
+
+#[tokio::test]
+async fn open_breaker_flags_in_flag_mode() {
+    let mock = MockApiLayer::start(Behavior::Error(500, "Down"));
+    let profanity = open_breaker(&mock, DegradedMode::Flag).await;
+
+    let detection = profanity.detect("shit".to_string()).await.unwrap();
+
+    assert!(detection.unchecked);
+    assert_eq!(detection.censored, "shit");
+    assert!(detection.apply(Policy::Censor).needs_review);
+    assert_eq!(mock.hits(), 2);
+}
+
+#[tokio::test]
+async fn open_breaker_censors_locally_in_local_filter_mode() {
+    let mock = MockApiLayer::start(Behavior::Error(500, "Down"));
+    let profanity = open_breaker(&mock, DegradedMode::LocalFilter).await;
+
+    let detection = profanity.detect("Oh shit, Shit!".to_string()).await.unwrap();
+
+    assert!(!detection.unchecked);
+    assert_eq!(detection.censored, "Oh ****, ****!");
+    assert_eq!(detection.bad_words.len(), 2);
+    assert_eq!(mock.hits(), 2);
+}
+
+#[tokio::test]
+async fn client_errors_do_not_open_the_breaker() {
+    let mock = MockApiLayer::start(Behavior::Error(400, "Bad request"));
+    let profanity = Profanity::new(&ProfanityArgs {
+        profanity_failure_threshold: 1,
+        ..profanity_args(mock.url())
+    });
+
+    assert!(matches!(
+        profanity.detect("fine".to_string()).await,
+        Err(Error::ClientError(_))
+    ));
+    mock.set_behavior(Behavior::Censor);
+    assert!(profanity.detect("fine".to_string()).await.is_ok());
+    assert_eq!(mock.hits(), 2);
+}
+
+#[tokio::test]
+async fn refused_keys_and_exhausted_quotas_open_the_breaker() {
+    for status in [401, 429] {
+        let mock = MockApiLayer::start(Behavior::Error(status, "Refused"));
+        let profanity = Profanity::new(&ProfanityArgs {
+            profanity_failure_threshold: 1,
+            ..profanity_args(mock.url())
+        });
+
+        assert!(matches!(
+            profanity.detect("fine".to_string()).await,
+            Err(Error::ClientError(_))
+        ));
+        assert!(profanity.unavailable_for().is_some(), "{}", status);
+    }
+}
+
+#[tokio::test]
+async fn review_applies_field_policies() {
+    let mock = MockApiLayer::start(Behavior::Censor);
+    let profanity = Profanity::new(&ProfanityArgs {
+        profanity_policies: vec![
+            "add_question=flag".parse::<PolicyRule>().unwrap(),
+            "add_question.title=reject".parse::<PolicyRule>().unwrap(),
+        ],
+        ..profanity_args(mock.url())
+    });
+
+    let review = profanity
+        .review(
+            "add_question",
+            vec![("title", "damn title".to_string()), ("content", "shit".to_string())],
+        )
+        .await
+        .unwrap();
+
+    match review.rejection() {
+        Some(Error::OffensiveContent(fields)) => {
+            assert_eq!(fields.len(), 1);
+            assert_eq!(fields[0].field, "title");
+            assert_eq!(fields[0].words, vec!["damn"]);
+        }
+        other => panic!("expected the title to be rejected, got {:?}", other),
+    }
+    assert_eq!(review.checked("content").content, "shit");
+    assert!(review.needs_review());
+    assert_eq!(profanity.policy("add_answer", "content"), Policy::Censor);
+}
+
+#[tokio::test]
+async fn reports_when_the_breaker_holds_calls_back() {
+    let mock = MockApiLayer::start(Behavior::Error(500, "Down"));
+    let closed = client(&mock);
+    assert_eq!(closed.unavailable_for(), None);
+
+    let profanity = open_breaker(&mock, DegradedMode::Reject).await;
+
+    assert!(matches!(profanity.unavailable_for(), Some(secs) if secs <= 30));
+    assert_eq!(mock.hits(), 2);
+}
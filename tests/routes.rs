@@ -0,0 +1,1327 @@
+mod support;
+
+use serde_json::{json, Value};
+use warp::http::StatusCode;
+use warp::test::request;
+
+use minimal_warp::cli::ModerationMode;
+use minimal_warp::moderation;
+use minimal_warp::profanity::Profanity;
+use minimal_warp::rate_limit::ClientAddr;
+
+use support::apilayer::{profanity_args, Behavior};
+use support::app::{TestApp, ADMIN_TOKEN};
+
+fn body(res: &warp::http::Response<warp::hyper::body::Bytes>) -> String {
+    String::from_utf8_lossy(res.body()).into_owned()
+}
+
+fn json_body(res: &warp::http::Response<warp::hyper::body::Bytes>) -> Value {
+    serde_json::from_slice(res.body()).expect("response is not JSON")
+}
+
+async fn add_question(app: &TestApp, title: &str, content: &str) {
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": title, "content": content, "tags": ["test"] }))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::CREATED, "{}", body(&res));
+}
+
+async fn get_questions(app: &TestApp, query: &str) -> Value {
+    let res = request()
+        .path(&format!("/v1/questions{}", query))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    json_body(&res)
+}
+
+#[tokio::test]
+async fn lists_added_questions() {
+    let app = TestApp::new().await;
+    add_question(&app, "First", "One").await;
+    add_question(&app, "Second", "Two").await;
+
+    let questions = get_questions(&app, "").await;
+
+    let titles: Vec<&str> = questions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|q| q["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, vec!["First", "Second"]);
+    assert_eq!(questions[0]["tags"], json!(["test"]));
+}
+
+#[tokio::test]
+async fn paginates_questions() {
+    let app = TestApp::new().await;
+    for title in ["First", "Second", "Third"] {
+        add_question(&app, title, "Content").await;
+    }
+
+    let questions = get_questions(&app, "?limit=1&offset=1").await;
+
+    assert_eq!(questions.as_array().unwrap().len(), 1);
+    assert_eq!(questions[0]["title"], "Second");
+}
+
+#[tokio::test]
+async fn paginates_questions_in_creation_order_after_updates() {
+    let app = TestApp::new().await;
+    for title in ["First", "Second", "Third"] {
+        add_question(&app, title, "Content").await;
+    }
+    sqlx::query("UPDATE questions SET content = 'Edited' WHERE title = 'First'")
+        .execute(&app.db.store.connection)
+        .await
+        .unwrap();
+
+    let questions = get_questions(&app, "?limit=1&offset=0").await;
+
+    assert_eq!(questions[0]["title"], "First");
+}
+
+#[tokio::test]
+async fn rejects_unparsable_pagination() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .path("/v1/questions?limit=ten&offset=0")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body(&res).starts_with("Cannot parse parameter"), "{}", body(&res));
+}
+
+#[tokio::test]
+async fn rejects_incomplete_pagination() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .path("/v1/questions?limit=10")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body(&res), "Missing parameter");
+}
+
+#[tokio::test]
+async fn censors_added_questions() {
+    let app = TestApp::new().await;
+
+    add_question(&app, "Damn", "What the shit").await;
+
+    let questions = get_questions(&app, "").await;
+    assert_eq!(questions[0]["title"], "****");
+    assert_eq!(questions[0]["content"], "What the ****");
+    assert_eq!(app.api.hits(), 2);
+}
+
+#[tokio::test]
+async fn rejects_offensive_questions_by_policy() {
+    let mut app = TestApp::new().await;
+    app.profanity = Profanity::new(&minimal_warp::cli::ProfanityArgs {
+        profanity_policies: vec!["add_question=reject".parse().unwrap()],
+        ..profanity_args(app.api.url())
+    });
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": "Fine", "content": "Oh shit" }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body(&res), "Content contains offensive words: content (shit)");
+    assert_eq!(get_questions(&app, "").await, json!([]));
+}
+
+#[tokio::test]
+async fn flags_offensive_questions_until_edited_clean() {
+    let mut app = TestApp::new().await;
+    app.profanity = Profanity::new(&minimal_warp::cli::ProfanityArgs {
+        profanity_policies: vec![
+            "add_question=flag".parse().unwrap(),
+            "update_question=flag".parse().unwrap(),
+        ],
+        ..profanity_args(app.api.url())
+    });
+    let needs_review = |id: i64| {
+        let connection = app.db.store.connection.clone();
+        async move {
+            sqlx::query_scalar::<_, bool>("SELECT needs_review FROM questions WHERE id = $1")
+                .bind(id as i32)
+                .fetch_one(&connection)
+                .await
+                .unwrap()
+        }
+    };
+
+    add_question(&app, "Fine", "Oh shit").await;
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+    assert!(needs_review(id).await);
+
+    let res = request()
+        .method("PUT")
+        .path(&format!("/v1/questions/{}", id))
+        .json(&json!({ "id": id, "title": "Fine", "content": "Oh well", "tags": null }))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    assert!(!needs_review(id).await);
+}
+
+#[tokio::test]
+async fn failing_profanity_api_is_an_internal_error() {
+    let app = TestApp::new().await;
+    app.api.set_behavior(Behavior::Error(500, "Down"));
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": "Title", "content": "Content" }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body(&res), "Internal Server Error");
+}
+
+#[tokio::test]
+async fn rejects_question_with_missing_field() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": "No content" }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body(&res).contains("missing field `content`"), "{}", body(&res));
+    assert_eq!(app.api.hits(), 0);
+}
+
+#[tokio::test]
+async fn rejects_malformed_json() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("content-type", "application/json")
+        .body("{\"title\": ")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body(&res).starts_with("Request body deserialize error"), "{}", body(&res));
+}
+
+#[tokio::test]
+async fn updates_questions() {
+    let app = TestApp::new().await;
+    add_question(&app, "Title", "Content").await;
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+
+    let res = request()
+        .method("PUT")
+        .path(&format!("/v1/questions/{}", id))
+        .json(&json!({ "id": id, "title": "New title", "content": "Damn", "tags": null }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    assert_eq!(
+        json_body(&res),
+        json!({ "id": id, "title": "New title", "content": "****", "tags": null })
+    );
+}
+
+#[tokio::test]
+async fn updating_unknown_question_fails() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("PUT")
+        .path("/v1/questions/4711")
+        .json(&json!({ "id": 4711, "title": "Title", "content": "Content", "tags": null }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body(&res), "Query could not be executed");
+}
+
+#[tokio::test]
+async fn clean_edits_show_rejected_questions_again() {
+    let app = TestApp::new().await;
+    add_question(&app, "Title", "Content").await;
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+    sqlx::query("UPDATE questions SET status = 'rejected' WHERE id = $1")
+        .bind(id as i32)
+        .execute(&app.db.store.connection)
+        .await
+        .unwrap();
+    assert_eq!(get_questions(&app, "").await, json!([]));
+
+    let res = request()
+        .method("PUT")
+        .path(&format!("/v1/questions/{}", id))
+        .json(&json!({ "id": id, "title": "Title", "content": "Damn", "tags": null }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    assert_eq!(get_questions(&app, "").await[0]["content"], "****");
+}
+
+#[tokio::test]
+async fn deletes_questions() {
+    let app = TestApp::new().await;
+    add_question(&app, "Title", "Content").await;
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+
+    let res = request()
+        .method("DELETE")
+        .path(&format!("/v1/questions/{}", id))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body(&res), format!("Question {} deleted", id));
+    assert_eq!(get_questions(&app, "").await, json!([]));
+}
+
+#[tokio::test]
+async fn rejects_non_numeric_question_id() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("DELETE")
+        .path("/v1/questions/first")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn adds_answers_from_forms() {
+    let app = TestApp::new().await;
+    add_question(&app, "Title", "Content").await;
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+
+    let res = request()
+        .method("POST")
+        .path("/v1/answers")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(format!("content=Oh+shit&question_id={}", id))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::CREATED, "{}", body(&res));
+    let answer = json_body(&res);
+    assert_eq!(answer["content"], "Oh ****");
+    assert_eq!(answer["question_id"], id);
+    assert_eq!(
+        res.headers()["location"],
+        format!("/v1/answers/{}", answer["id"]).as_str()
+    );
+    let content: String = sqlx::query_scalar("SELECT content FROM answers")
+        .fetch_one(&app.db.store.connection)
+        .await
+        .unwrap();
+    assert_eq!(content, "Oh ****");
+}
+
+#[tokio::test]
+async fn rejects_answer_with_invalid_form() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/answers")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("content=Answer&question_id=first")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body(&res).starts_with("Request body deserialize error"), "{}", body(&res));
+}
+
+#[tokio::test]
+async fn rejects_answer_to_unknown_question() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/answers")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("content=Answer&question_id=4711")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body(&res), "Query could not be executed");
+}
+
+#[tokio::test]
+async fn adds_questions_from_forms() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("content-type", "application/x-www-form-urlencoded; charset=utf-8")
+        .body("title=Form&content=Damn+it&tags=a&tags=b")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::CREATED, "{}", body(&res));
+    let questions = get_questions(&app, "").await;
+    assert_eq!(questions[0]["content"], "**** it");
+    assert_eq!(questions[0]["tags"], json!(["a", "b"]));
+}
+
+#[tokio::test]
+async fn adds_answers_from_json_and_msgpack() {
+    let app = TestApp::new().await;
+    add_question(&app, "Title", "Content").await;
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+    let answer = json!({ "content": "Answer", "question_id": id });
+
+    let json = request()
+        .method("POST")
+        .path("/v1/answers")
+        .json(&answer)
+        .reply(&app.routes())
+        .await;
+    let msgpack = request()
+        .method("POST")
+        .path("/v1/answers")
+        .header("content-type", "application/x-msgpack")
+        .body(rmp_serde::to_vec_named(&answer).unwrap())
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(json.status(), StatusCode::CREATED, "{}", body(&json));
+    assert_eq!(msgpack.status(), StatusCode::CREATED, "{}", body(&msgpack));
+    let count: i64 = sqlx::query_scalar("SELECT count(*) FROM answers")
+        .fetch_one(&app.db.store.connection)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+}
+
+#[tokio::test]
+async fn rejects_unsupported_content_type() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("content-type", "text/plain")
+        .body("title=Title&content=Content")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        res.headers()["accept"],
+        "application/json, application/x-www-form-urlencoded, application/msgpack"
+    );
+    assert!(body(&res).starts_with("Unsupported content type `text/plain`"), "{}", body(&res));
+    assert_eq!(app.api.hits(), 0);
+}
+
+#[tokio::test]
+async fn updates_questions_in_msgpack() {
+    let app = TestApp::new().await;
+    add_question(&app, "Title", "Content").await;
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+    let question = json!({ "id": id, "title": "New title", "content": "Content", "tags": ["a"] });
+
+    let res = request()
+        .method("PUT")
+        .path(&format!("/v1/questions/{}", id))
+        .header("content-type", "application/msgpack")
+        .header("accept", "application/msgpack")
+        .body(rmp_serde::to_vec_named(&question).unwrap())
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    assert_eq!(res.headers()["content-type"], "application/msgpack");
+    assert_eq!(res.headers()["vary"], "accept");
+    let updated: Value = rmp_serde::from_slice(res.body()).unwrap();
+    assert_eq!(updated, question);
+}
+
+#[tokio::test]
+async fn lists_questions_as_csv() {
+    let app = TestApp::new().await;
+    add_question(&app, "First", "One, two").await;
+
+    let res = request()
+        .path("/v1/questions")
+        .header("accept", "application/json;q=0.5, text/*")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    assert_eq!(res.headers()["content-type"], "text/csv");
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+    assert_eq!(
+        body(&res),
+        format!("id,title,content,tags\n{},First,\"One, two\",test\n", id)
+    );
+}
+
+#[tokio::test]
+async fn prefers_json_when_accepting_anything() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .path("/v1/questions")
+        .header("accept", "*/*")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/json");
+    assert_eq!(json_body(&res), json!([]));
+}
+
+#[tokio::test]
+async fn rejects_unacceptable_response_types() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .path("/v1/questions")
+        .header("accept", "text/html, application/json;q=0")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+    assert!(
+        body(&res).contains("application/json, application/msgpack, text/csv"),
+        "{}",
+        body(&res)
+    );
+}
+
+#[tokio::test]
+async fn creates_questions_at_their_location() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("accept", "application/msgpack")
+        .json(&json!({ "title": "Title", "content": "Damn", "tags": null }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::CREATED, "{}", body(&res));
+    assert_eq!(res.headers()["content-type"], "application/msgpack");
+    let question: Value = rmp_serde::from_slice(res.body()).unwrap();
+    assert_eq!(question, get_questions(&app, "").await[0]);
+    assert_eq!(question["content"], "****");
+    assert_eq!(
+        res.headers()["location"],
+        format!("/v1/questions/{}", question["id"]).as_str()
+    );
+}
+
+#[tokio::test]
+async fn serves_created_content_at_its_location() {
+    let app = TestApp::new().await;
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": "Title", "content": "Damn" }))
+        .reply(&app.routes())
+        .await;
+    let question = json_body(&res);
+
+    let res = request()
+        .path(res.headers()["location"].to_str().unwrap())
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    assert_eq!(json_body(&res), question);
+
+    let res = request()
+        .method("POST")
+        .path("/v1/answers")
+        .json(&json!({ "content": "Shit", "question_id": question["id"] }))
+        .reply(&app.routes())
+        .await;
+    let answer = json_body(&res);
+
+    let res = request()
+        .path(res.headers()["location"].to_str().unwrap())
+        .header("accept", "application/msgpack")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    assert_eq!(rmp_serde::from_slice::<Value>(res.body()).unwrap(), answer);
+    assert_eq!(answer["content"], "****");
+
+    let legacy = request()
+        .path(&format!("/answers/{}", answer["id"]))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(legacy.status(), StatusCode::OK);
+    assert_eq!(json_body(&legacy), answer);
+}
+
+#[tokio::test]
+async fn does_not_serve_missing_or_pending_content() {
+    let mut app = TestApp::new().await;
+    app.args.moderation = ModerationMode::Async;
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": "Title", "content": "Content" }))
+        .reply(&app.routes())
+        .await;
+    let id = json_body(&res)["id"].as_i64().unwrap();
+
+    let pending = request()
+        .path(res.headers()["location"].to_str().unwrap())
+        .reply(&app.routes())
+        .await;
+    let missing = request().path("/v1/answers/4711").reply(&app.routes()).await;
+
+    assert_eq!(pending.status(), StatusCode::NOT_FOUND);
+    assert!(body(&pending).starts_with(&format!("Question {} not found", id)), "{}", body(&pending));
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    assert!(body(&missing).starts_with("Answer 4711 not found"), "{}", body(&missing));
+}
+
+async fn post_question(
+    app: &TestApp,
+    key: &str,
+    title: &str,
+) -> warp::http::Response<warp::hyper::body::Bytes> {
+    request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("idempotency-key", key)
+        .json(&json!({ "title": title, "content": "Content" }))
+        .reply(&app.routes())
+        .await
+}
+
+#[tokio::test]
+async fn replays_requests_with_the_same_idempotency_key() {
+    let app = TestApp::new().await;
+
+    let first = post_question(&app, "key-1", "Title").await;
+    let retry = post_question(&app, "key-1", "Title").await;
+    let other = post_question(&app, "key-2", "Title").await;
+
+    assert_eq!(first.status(), StatusCode::CREATED, "{}", body(&first));
+    assert_eq!(retry.status(), StatusCode::CREATED);
+    assert_eq!(retry.body(), first.body());
+    assert_eq!(retry.headers()["location"], first.headers()["location"]);
+    assert_eq!(retry.headers()["content-type"], "application/json");
+    assert_ne!(other.body(), first.body());
+    assert_eq!(get_questions(&app, "").await.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn rejects_idempotency_key_reused_for_another_request() {
+    let app = TestApp::new().await;
+    post_question(&app, "key", "Title").await;
+
+    let res = post_question(&app, "key", "Other title").await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body(&res).starts_with("Idempotency key was already used"), "{}", body(&res));
+    assert_eq!(get_questions(&app, "").await.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn rejects_idempotency_key_in_use() {
+    let app = TestApp::new().await;
+    app.api.set_behavior(Behavior::Slow(std::time::Duration::from_millis(300)));
+
+    let (first, second) = tokio::join!(post_question(&app, "key", "Title"), async {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        post_question(&app, "key", "Title").await
+    });
+
+    assert_eq!(first.status(), StatusCode::CREATED, "{}", body(&first));
+    assert_eq!(second.status(), StatusCode::CONFLICT, "{}", body(&second));
+}
+
+#[tokio::test]
+async fn releases_idempotency_key_after_failure() {
+    let app = TestApp::new().await;
+    app.api.set_behavior(Behavior::Error(500, "Down"));
+
+    let failed = post_question(&app, "key", "Title").await;
+    app.api.set_behavior(Behavior::Censor);
+    let retry = post_question(&app, "key", "Title").await;
+
+    assert_eq!(failed.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(retry.status(), StatusCode::CREATED, "{}", body(&retry));
+}
+
+#[tokio::test]
+async fn rejects_invalid_idempotency_key() {
+    let app = TestApp::new().await;
+
+    let empty = post_question(&app, "", "Title").await;
+    let long = post_question(&app, &"k".repeat(256), "Title").await;
+
+    assert_eq!(empty.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(long.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(app.api.hits(), 0);
+}
+
+#[tokio::test]
+async fn scopes_idempotency_keys_per_client() {
+    let app = TestApp::new().await;
+    let routes = app.routes();
+    let post = |client: [u8; 4]| {
+        request()
+            .method("POST")
+            .path("/v1/questions")
+            .header("idempotency-key", "key")
+            .extension(ClientAddr((client, 4711).into()))
+            .json(&json!({ "title": "Title", "content": "Content" }))
+            .reply(&routes)
+    };
+
+    let first = post([192, 0, 2, 1]).await;
+    let other = post([192, 0, 2, 2]).await;
+    let retry = post([192, 0, 2, 1]).await;
+
+    assert_eq!(first.status(), StatusCode::CREATED, "{}", body(&first));
+    assert_eq!(other.status(), StatusCode::CREATED, "{}", body(&other));
+    assert_ne!(other.body(), first.body());
+    assert_eq!(retry.body(), first.body());
+}
+
+#[tokio::test]
+async fn rejects_idempotency_key_reused_with_another_format() {
+    let app = TestApp::new().await;
+    post_question(&app, "key", "Title").await;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .header("idempotency-key", "key")
+        .header("accept", "application/msgpack")
+        .json(&json!({ "title": "Title", "content": "Content" }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{}", body(&res));
+}
+
+#[tokio::test]
+async fn takes_over_and_sweeps_expired_idempotency_keys() {
+    let app = TestApp::new().await;
+    post_question(&app, "old", "Title").await;
+    post_question(&app, "new", "Title").await;
+    sqlx::query("UPDATE idempotency_keys SET created_at = NOW() - INTERVAL '2 days' WHERE key = 'old'")
+        .execute(&app.db.store.connection)
+        .await
+        .unwrap();
+
+    let res = post_question(&app, "old", "Other title").await;
+
+    assert_eq!(res.status(), StatusCode::CREATED, "{}", body(&res));
+    assert_eq!(json_body(&res)["title"], "Other title");
+
+    sqlx::query("UPDATE idempotency_keys SET created_at = NOW() - INTERVAL '2 days' WHERE key = 'old'")
+        .execute(&app.db.store.connection)
+        .await
+        .unwrap();
+    let ttl = std::time::Duration::from_secs(app.args.idempotency_ttl);
+    assert_eq!(app.db.store.sweep_idempotency_keys(ttl).await.unwrap(), 1);
+    let keys: Vec<String> = sqlx::query_scalar("SELECT key FROM idempotency_keys")
+        .fetch_all(&app.db.store.connection)
+        .await
+        .unwrap();
+    assert_eq!(keys, vec!["new"]);
+}
+
+#[tokio::test]
+async fn queues_questions_for_moderation() {
+    let mut app = TestApp::new().await;
+    app.args.moderation = ModerationMode::Async;
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions")
+        .json(&json!({ "title": "Title", "content": "Content" }))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let id = json_body(&res)["id"].as_i64().unwrap();
+    assert_eq!(res.headers()["location"], format!("/v1/questions/{}", id).as_str());
+    assert_eq!(get_questions(&app, "").await, json!([]));
+    assert_eq!(app.api.hits(), 0);
+}
+
+#[tokio::test]
+async fn audits_offensive_content_with_the_content() {
+    let app = TestApp::new().await;
+    let res = post_question(&app, "key", "Damn").await;
+    assert_eq!(res.status(), StatusCode::CREATED, "{}", body(&res));
+
+    let audited: Vec<(Option<i32>, String)> =
+        sqlx::query_as("SELECT question_id, field FROM profanity_audit")
+            .fetch_all(&app.db.store.connection)
+            .await
+            .unwrap();
+    assert_eq!(audited, vec![(json_body(&res)["id"].as_i64().map(|id| id as i32), "title".to_string())]);
+
+    // Without an audit trail, nothing is stored
+    sqlx::query("DROP TABLE profanity_audit")
+        .execute(&app.db.store.connection)
+        .await
+        .unwrap();
+    let res = post_question(&app, "other", "Damn").await;
+
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY, "{}", body(&res));
+    assert_eq!(get_questions(&app, "").await.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn audits_moderated_content_once() {
+    let mut app = TestApp::new().await;
+    app.args.moderation = ModerationMode::Async;
+    let res = post_question(&app, "key", "Damn").await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED, "{}", body(&res));
+
+    let workers = moderation::spawn_workers(
+        1,
+        app.db.store.clone(),
+        app.profanity.clone(),
+        app.readiness.clone(),
+        5,
+    );
+    for _ in 0..100 {
+        if get_questions(&app, "").await.as_array().unwrap().len() == 1 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    app.readiness.shutting_down();
+    futures::future::join_all(workers).await;
+
+    assert_eq!(get_questions(&app, "").await[0]["title"], "****");
+    let audited: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM profanity_audit WHERE question_id IS NOT NULL")
+        .fetch_one(&app.db.store.connection)
+        .await
+        .unwrap();
+    assert_eq!(audited, 1);
+}
+
+#[tokio::test]
+async fn rate_limits_by_client_address() {
+    let mut app = TestApp::new().await;
+    app.args.rate_limits = vec!["get_questions=1/60".parse().unwrap()];
+    let routes = app.routes();
+    let client = ClientAddr(([192, 0, 2, 1], 4711).into());
+
+    let first = request()
+        .path("/v1/questions")
+        .extension(client)
+        .reply(&routes)
+        .await;
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(first.headers()["ratelimit-remaining"], "0");
+
+    let second = request()
+        .path("/v1/questions")
+        .extension(client)
+        .reply(&routes)
+        .await;
+    assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(second.headers().contains_key("retry-after"));
+
+    // Another client has a bucket of its own
+    let other = request()
+        .path("/v1/questions")
+        .extension(ClientAddr(([192, 0, 2, 2], 4711).into()))
+        .reply(&routes)
+        .await;
+    assert_eq!(other.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn rate_limits_ipv6_clients_by_their_64_bit_prefix() {
+    let mut app = TestApp::new().await;
+    app.args.rate_limits = vec!["get_questions=1/60".parse().unwrap()];
+    let routes = app.routes();
+    let status = |addr: &str| {
+        let client = ClientAddr(format!("[{}]:4711", addr).parse().unwrap());
+        let routes = routes.clone();
+        async move {
+            request()
+                .path("/v1/questions")
+                .extension(client)
+                .reply(&routes)
+                .await
+                .status()
+        }
+    };
+
+    assert_eq!(status("2001:db8:0:1::1").await, StatusCode::OK);
+    assert_eq!(status("2001:db8:0:1::2").await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(status("2001:db8:0:2::1").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn takes_the_client_from_trusted_proxies() {
+    let mut app = TestApp::new().await;
+    app.args.rate_limits = vec!["get_questions=1/60".parse().unwrap()];
+    app.args.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+    let routes = app.routes();
+    let status = |peer: [u8; 4], forwarded: &str| {
+        let request = request()
+            .path("/v1/questions")
+            .extension(ClientAddr((peer, 4711).into()))
+            .header("x-forwarded-for", forwarded);
+        let routes = routes.clone();
+        async move { request.reply(&routes).await.status() }
+    };
+
+    assert_eq!(status([10, 0, 0, 1], "192.0.2.1").await, StatusCode::OK);
+    // Through another proxy, with an address the client made up in front
+    assert_eq!(
+        status([10, 0, 0, 2], "198.51.100.9, 192.0.2.1, 10.0.0.1").await,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(status([10, 0, 0, 1], "192.0.2.2").await, StatusCode::OK);
+    // Untrusted clients can't pick another bucket
+    assert_eq!(status([192, 0, 2, 3], "192.0.2.4").await, StatusCode::OK);
+    assert_eq!(status([192, 0, 2, 3], "192.0.2.5").await, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn sweeps_shared_rate_limit_buckets_once_full() {
+    let app = TestApp::new().await;
+    let store = &app.db.store;
+    sqlx::query(
+        "INSERT INTO rate_limit_buckets (route, client, tokens, updated_at) VALUES
+        ('add_question', 'idle', 0, NOW() - INTERVAL '2 minutes'),
+        ('add_question', 'active', 0, NOW()),
+        ('get_questions', 'unlimited', 0, NOW())",
+    )
+    .execute(&store.connection)
+    .await
+    .unwrap();
+
+    let swept = store
+        .sweep_rate_limit_buckets(&["add_question=10/60".parse().unwrap()])
+        .await
+        .unwrap();
+
+    assert_eq!(swept, 2);
+    let clients: Vec<String> = sqlx::query_scalar("SELECT client FROM rate_limit_buckets")
+        .fetch_all(&store.connection)
+        .await
+        .unwrap();
+    assert_eq!(clients, vec!["active"]);
+}
+
+#[tokio::test]
+async fn exports_imported_questions() {
+    let app = TestApp::new().await;
+    let lines = [
+        json!({ "title": "Imported", "content": "Content", "tags": null,
+                "answers": [{ "content": "Answer" }] }),
+        json!({ "title": "Broken" }),
+    ];
+    let ndjson: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+
+    let res = request()
+        .method("POST")
+        .path("/v1/admin/import")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .body(ndjson)
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    let report = json_body(&res);
+    assert_eq!(report["questions"], 1);
+    assert_eq!(report["answers"], 1);
+    assert_eq!(report["errors"][0]["line"], 2);
+
+    let res = request()
+        .path("/v1/admin/export")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+    let exported: Vec<Value> = body(&res)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0]["title"], "Imported");
+    assert_eq!(exported[0]["answers"][0]["content"], "Answer");
+}
+
+#[tokio::test]
+async fn restores_moderation_status_on_import() {
+    let app = TestApp::new().await;
+    add_question(&app, "Pending", "Content").await;
+    add_question(&app, "Rejected", "Content").await;
+    for update in [
+        "UPDATE questions SET status = 'pending_moderation' WHERE title = 'Pending'",
+        "UPDATE questions SET status = 'rejected', needs_review = true WHERE title = 'Rejected'",
+    ] {
+        sqlx::query(update).execute(&app.db.store.connection).await.unwrap();
+    }
+
+    let export = request()
+        .path("/v1/admin/export")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .reply(&app.routes())
+        .await;
+    let exported: Vec<Value> = body(&export)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(exported[0]["status"], "pending_moderation");
+    assert_eq!(exported[1]["status"], "rejected");
+    assert_eq!(exported[1]["needs_review"], true);
+
+    sqlx::query("TRUNCATE questions CASCADE")
+        .execute(&app.db.store.connection)
+        .await
+        .unwrap();
+    let res = request()
+        .method("POST")
+        .path("/v1/admin/import")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .body(body(&export))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+
+    assert_eq!(get_questions(&app, "").await, json!([]));
+    let rows: Vec<(String, String, bool)> =
+        sqlx::query_as("SELECT title, status, needs_review FROM questions ORDER BY id")
+            .fetch_all(&app.db.store.connection)
+            .await
+            .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            ("Pending".to_string(), "pending_moderation".to_string(), false),
+            ("Rejected".to_string(), "rejected".to_string(), true),
+        ]
+    );
+    let jobs: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM moderation_jobs")
+        .fetch_one(&app.db.store.connection)
+        .await
+        .unwrap();
+    assert_eq!(jobs, 1);
+}
+
+#[tokio::test]
+async fn requires_the_admin_token() {
+    let mut app = TestApp::new().await;
+
+    for path in ["/v1/admin/export", "/admin/export"] {
+        let res = request().path(path).reply(&app.routes()).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{}", path);
+        assert_eq!(res.headers()["www-authenticate"], "Bearer");
+
+        let res = request()
+            .path(path)
+            .header("authorization", "Bearer not-the-token")
+            .reply(&app.routes())
+            .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{}", path);
+    }
+
+    let res = request()
+        .method("POST")
+        .path("/v1/admin/import")
+        .body(format!("{}\n", json!({ "id": 1, "title": "Overwritten", "content": "Content" })))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(app.db.store.get_question(1).await.unwrap().is_none());
+
+    // Without a configured token, nobody gets in
+    app.args.admin_token = None;
+    let res = request()
+        .path("/v1/admin/export")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn dry_run_imports_leave_sequences_alone() {
+    let app = TestApp::new().await;
+    add_question(&app, "Existing", "Content").await;
+
+    let sequences = || async {
+        sqlx::query_as::<_, (i64, bool, i64, bool)>(
+            "SELECT q.last_value, q.is_called, a.last_value, a.is_called
+            FROM questions_id_seq q, answers_id_seq a",
+        )
+        .fetch_one(&app.db.store.connection)
+        .await
+        .unwrap()
+    };
+    let before = sequences().await;
+
+    let lines = [
+        json!({ "id": 50, "title": "Explicit", "content": "Content",
+                "answers": [{ "id": 70, "content": "Answer" }] }),
+        json!({ "title": "Generated", "content": "Content",
+                "answers": [{ "content": "Answer" }] }),
+    ];
+    let ndjson: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    let res = request()
+        .method("POST")
+        .path("/v1/admin/import?dry_run=true")
+        .header("authorization", format!("Bearer {}", ADMIN_TOKEN))
+        .body(ndjson)
+        .reply(&app.routes())
+        .await;
+    assert_eq!(res.status(), StatusCode::OK, "{}", body(&res));
+    let report = json_body(&res);
+    assert_eq!(report["questions"], 2, "{}", report);
+    assert_eq!(report["errors"], json!([]));
+
+    assert_eq!(sequences().await, before);
+    assert!(app.db.store.get_question(50).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn reports_health() {
+    let app = TestApp::new().await;
+
+    let live = request().path("/health/live").reply(&app.routes()).await;
+    assert_eq!(live.status(), StatusCode::OK);
+    assert_eq!(json_body(&live), json!({ "status": "up" }));
+
+    let ready = request().path("/health/ready").reply(&app.routes()).await;
+    assert_eq!(ready.status(), StatusCode::OK, "{}", body(&ready));
+    assert_eq!(json_body(&ready)["status"], "up");
+
+    app.readiness.shutting_down();
+    let ready = request().path("/health/ready").reply(&app.routes()).await;
+    assert_eq!(ready.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json_body(&ready)["checks"]["server"]["status"], "down");
+}
+
+#[tokio::test]
+async fn readiness_reflects_the_breaker_and_startup_migrations() {
+    let app = TestApp::new().await;
+
+    let ready = request().path("/health/ready").reply(&app.routes()).await;
+    assert_eq!(json_body(&ready)["checks"]["profanity"]["status"], "up");
+    // Probes don't call the profanity API
+    assert_eq!(app.api.hits(), 0);
+
+    app.api.set_behavior(Behavior::Error(500, "Down"));
+    for _ in 0..5 {
+        assert!(app.profanity.detect("fine".to_string()).await.is_err());
+    }
+    let ready = request().path("/health/ready").reply(&app.routes()).await;
+    assert_eq!(ready.status(), StatusCode::OK);
+    assert_eq!(json_body(&ready)["status"], "degraded");
+    assert_eq!(json_body(&ready)["checks"]["profanity"]["status"], "degraded");
+    assert_eq!(app.api.hits(), 5);
+
+    app.readiness.migrations_checked(Ok(2));
+    let ready = request().path("/health/ready").reply(&app.routes()).await;
+    assert_eq!(ready.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json_body(&ready)["checks"]["migrations"]["error"], "2 pending migrations");
+}
+
+#[tokio::test]
+async fn serves_metrics() {
+    let app = TestApp::new().await;
+
+    let res = request().path("/metrics").reply(&app.routes()).await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(body(&res).contains("profanity_cache_entries"), "{}", body(&res));
+}
+
+#[tokio::test]
+async fn labels_request_metrics_by_route() {
+    let mut app = TestApp::new().await;
+    app.args.cors_origins = vec!["https://example.com".parse().unwrap()];
+
+    let delete = request()
+        .method("DELETE")
+        .path("/v1/questions/4242")
+        .reply(&app.routes())
+        .await;
+    let unknown = request().path("/metrics-label-test/4242").reply(&app.routes()).await;
+    assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    let forbidden = request()
+        .method("DELETE")
+        .path("/v1/questions/4242")
+        .header("origin", "https://elsewhere.example")
+        .reply(&app.routes())
+        .await;
+    assert_eq!(forbidden.status(), StatusCode::FORBIDDEN);
+
+    let res = request().path("/metrics").reply(&app.routes()).await;
+    let metrics = body(&res);
+    let deleted = format!(
+        r#"http_requests_total{{method="DELETE",route="delete_question",status="{}"}}"#,
+        delete.status().as_str()
+    );
+    assert!(metrics.contains(&deleted), "{}", metrics);
+    assert!(
+        metrics.contains(r#"http_requests_total{method="DELETE",route="unmatched",status="403"}"#),
+        "{}",
+        metrics
+    );
+    assert!(!metrics.contains("metrics-label-test"), "{}", metrics);
+    assert!(!metrics.contains(r#"route="delete_question",status="403""#), "{}", metrics);
+}
+
+#[tokio::test]
+async fn allows_cors_preflight() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("OPTIONS")
+        .path("/v1/questions")
+        .header("origin", "https://example.com")
+        .header("access-control-request-method", "PUT")
+        .header("access-control-request-headers", "content-type")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["access-control-allow-origin"], "https://example.com");
+}
+
+#[tokio::test]
+async fn rejects_cors_preflight_with_unknown_header() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("OPTIONS")
+        .path("/v1/questions")
+        .header("origin", "https://example.com")
+        .header("access-control-request-method", "PUT")
+        .header("access-control-request-headers", "x-custom")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert!(
+        body(&res).starts_with(
+            "CORS request from `https://example.com` forbidden: header `x-custom` not allowed"
+        ),
+        "{}",
+        body(&res)
+    );
+}
+
+#[tokio::test]
+async fn rejects_cors_preflight_with_unknown_method() {
+    let app = TestApp::new().await;
+
+    let res = request()
+        .method("OPTIONS")
+        .path("/v1/questions")
+        .header("origin", "https://example.com")
+        .header("access-control-request-method", "PATCH")
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert!(
+        body(&res).starts_with(
+            "CORS request from `https://example.com` forbidden: method `PATCH` not allowed"
+        ),
+        "{}",
+        body(&res)
+    );
+}
+
+#[tokio::test]
+async fn falls_back_to_not_found() {
+    let app = TestApp::new().await;
+
+    let requests = [
+        ("GET", "/unknown"),
+        ("PATCH", "/v1/questions"),
+        ("GET", "/v1/questions/1/answers"),
+        ("GET", "/v2/questions"),
+        ("GET", "/v1/health/live"),
+    ];
+    for (method, path) in requests {
+        let res = request().method(method).path(path).reply(&app.routes()).await;
+
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{} {}", method, path);
+        assert_eq!(body(&res), "Route not found");
+    }
+}
+
+#[tokio::test]
+async fn serves_unversioned_paths_as_deprecated_aliases() {
+    let mut app = TestApp::new().await;
+    app.args.legacy_sunset = "2027-01-31T12:00:00Z".parse().unwrap();
+    add_question(&app, "Title", "Content").await;
+
+    let legacy = request().path("/questions").reply(&app.routes()).await;
+    let v1 = request().path("/v1/questions").reply(&app.routes()).await;
+
+    assert_eq!(legacy.status(), StatusCode::OK);
+    assert_eq!(legacy.body(), v1.body());
+    assert_eq!(legacy.headers()["deprecation"], "@1792281600");
+    assert_eq!(legacy.headers()["sunset"], "Sun, 31 Jan 2027 12:00:00 GMT");
+    assert_eq!(legacy.headers()["link"], "</v1/questions>; rel=\"successor-version\"");
+    for header in ["deprecation", "sunset", "link"] {
+        assert!(!v1.headers().contains_key(header), "/v1 sent {}", header);
+    }
+}
+
+#[tokio::test]
+async fn unversioned_paths_link_to_their_successor() {
+    let app = TestApp::new().await;
+    add_question(&app, "Title", "Content").await;
+    let id = get_questions(&app, "").await[0]["id"].as_i64().unwrap();
+    let res = request()
+        .method("DELETE")
+        .path(&format!("/questions/{}", id))
+        .reply(&app.routes())
+        .await;
+
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        res.headers()["link"],
+        format!("</v1/questions/{}>; rel=\"successor-version\"", id).as_str()
+    );
+}
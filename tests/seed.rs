@@ -0,0 +1,92 @@
+mod support;
+
+use std::path::PathBuf;
+
+use minimal_warp::seed::{self, SeedError};
+use support::db::TestDb;
+
+/// Write `content` to a temporary file named `name`
+fn fixture_file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("minimal-warp-{}-{}", std::process::id(), name));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn loads_fixtures_with_and_without_ids() {
+    let db = TestDb::new().await;
+    let path = fixture_file(
+        "fixture.yaml",
+        r#"
+questions:
+  - id: 10
+    title: Known id
+    content: Kept as it is
+    tags: [seed]
+    answers:
+      - id: 20
+        content: Known answer
+  - title: New id
+    content: Numbered after the known one
+    answers:
+      - content: New answer
+"#,
+    );
+
+    let fixture = seed::read_fixture(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let report = seed::load_fixture(&db.store, fixture, None).await.unwrap();
+
+    assert_eq!((report.questions, report.answers), (2, 2));
+    let known = db.store.get_question(10).await.unwrap().unwrap();
+    assert_eq!(known.title, "Known id");
+    assert_eq!(known.tags, Some(vec!["seed".to_string()]));
+    assert_eq!(db.store.get_answer(20).await.unwrap().unwrap().content, "Known answer");
+    // The sequences were moved past the explicit ids before inserting
+    assert_eq!(db.store.get_question(11).await.unwrap().unwrap().title, "New id");
+    assert_eq!(db.store.get_answer(21).await.unwrap().unwrap().content, "New answer");
+}
+
+#[tokio::test]
+async fn moves_sequences_past_trailing_explicit_ids() {
+    let db = TestDb::new().await;
+    let path = fixture_file(
+        "trailing.json",
+        r#"{ "questions": [{ "id": 5, "title": "Five", "content": "Last record", "answers": [] }] }"#,
+    );
+
+    let fixture = seed::read_fixture(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    seed::load_fixture(&db.store, fixture, None).await.unwrap();
+    seed::generate_questions(&db.store, 1).await.unwrap();
+
+    assert!(db.store.get_question(6).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn generates_random_questions() {
+    let db = TestDb::new().await;
+
+    let report = seed::generate_questions(&db.store, 5).await.unwrap();
+
+    assert_eq!(report.questions, 5);
+    let questions = db.store.get_questions(None, 0).await.unwrap();
+    assert_eq!(questions.len(), 5);
+    assert!(questions.iter().all(|q| q.title.starts_with("How does ")));
+}
+
+#[test]
+fn rejects_unreadable_fixtures() {
+    let path = fixture_file("broken.json", "{ \"questions\": [");
+    let broken = seed::read_fixture(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(broken, Err(SeedError::Json(_))), "{:?}", broken);
+
+    let path = fixture_file("broken.yaml", "questions: [[");
+    let broken = seed::read_fixture(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(broken, Err(SeedError::Yaml(_))), "{:?}", broken);
+
+    let missing = seed::read_fixture(&std::env::temp_dir().join("minimal-warp-missing.json"));
+    assert!(matches!(missing, Err(SeedError::Io(_))), "{:?}", missing);
+}
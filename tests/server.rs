@@ -0,0 +1,240 @@
+mod support;
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tokio_util::sync::CancellationToken;
+use warp::http::{Request, StatusCode};
+use warp::hyper::client::conn::{self, SendRequest};
+use warp::hyper::Body;
+use warp::test::request;
+
+use minimal_warp::{routes, server, tls};
+use support::app::TestApp;
+
+/// A self-signed certificate for `localhost`, written to `dir`
+struct SelfSigned {
+    cert: PathBuf,
+    key: PathBuf,
+    der: CertificateDer<'static>,
+}
+
+impl SelfSigned {
+    fn write(dir: &Path) -> Self {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+        std::fs::write(&cert, generated.cert.pem()).unwrap();
+        std::fs::write(&key, generated.key_pair.serialize_pem()).unwrap();
+        SelfSigned { cert, key, der: generated.cert.der().clone() }
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("minimal-warp-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Serve the app on `addrs`, returning the bound addresses
+fn start(
+    app: &TestApp,
+    addrs: &[&str],
+    tls: Option<&Arc<tls::Certificates>>,
+    stop: &CancellationToken,
+) -> Vec<SocketAddr> {
+    let listeners: Vec<_> =
+        addrs.iter().map(|addr| server::bind(addr.parse().unwrap()).unwrap()).collect();
+    let bound = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+    let acceptor = tls.map(|certificates| certificates.acceptor().unwrap());
+    tokio::spawn(server::serve(
+        server::incoming(listeners, acceptor, stop.clone()),
+        app.routes(),
+        stop.clone(),
+    ));
+    bound
+}
+
+async fn connect_plain(addr: SocketAddr) -> SendRequest<Body> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (sender, connection) = conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    sender
+}
+
+/// Connect over TLS trusting only `root`, returning the certificate served
+async fn connect_tls(
+    addr: SocketAddr,
+    root: &CertificateDer<'static>,
+) -> (SendRequest<Body>, CertificateDer<'static>) {
+    let mut roots = RootCertStore::empty();
+    roots.add(root.clone()).unwrap();
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let stream = TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .unwrap();
+    let served = stream.get_ref().1.peer_certificates().unwrap()[0].clone().into_owned();
+    let (sender, connection) = conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    (sender, served)
+}
+
+async fn get(sender: &mut SendRequest<Body>, path: &str) -> StatusCode {
+    let req = Request::get(path).header("host", "localhost").body(Body::empty()).unwrap();
+    sender.send_request(req).await.unwrap().status()
+}
+
+#[tokio::test]
+async fn serves_plain_http_on_every_listener() {
+    let app = TestApp::new().await;
+    let stop = CancellationToken::new();
+
+    let addrs = start(&app, &["127.0.0.1:0", "[::1]:0"], None, &stop);
+
+    for addr in addrs {
+        let mut sender = connect_plain(addr).await;
+        assert_eq!(get(&mut sender, "/health/live").await, StatusCode::OK, "{}", addr);
+    }
+    stop.cancel();
+}
+
+#[tokio::test]
+async fn stops_accepting_once_stopped() {
+    let app = TestApp::new().await;
+    let stop = CancellationToken::new();
+    let addr = start(&app, &["127.0.0.1:0"], None, &stop)[0];
+
+    stop.cancel();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn serves_https() {
+    let app = TestApp::new().await;
+    let dir = temp_dir("serves-https");
+    let cert = SelfSigned::write(&dir);
+    let certificates = tls::Certificates::load(&cert.cert, &cert.key).unwrap();
+    let stop = CancellationToken::new();
+    let addr = start(&app, &["127.0.0.1:0"], Some(&certificates), &stop)[0];
+
+    let (mut sender, served) = connect_tls(addr, &cert.der).await;
+
+    assert_eq!(served, cert.der);
+    assert_eq!(get(&mut sender, "/health/live").await, StatusCode::OK);
+    stop.cancel();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn reloads_changed_certificates_without_dropping_connections() {
+    let app = TestApp::new().await;
+    let dir = temp_dir("reload");
+    let old = SelfSigned::write(&dir);
+    let certificates = tls::Certificates::load(&old.cert, &old.key).unwrap();
+    let stop = CancellationToken::new();
+    let addr = start(&app, &["127.0.0.1:0"], Some(&certificates), &stop)[0];
+    certificates.clone().watch(Duration::from_millis(50), stop.clone());
+    let (mut established, _) = connect_tls(addr, &old.der).await;
+
+    // Half-written files keep the old certificate
+    std::fs::write(&old.key, "").unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(connect_tls(addr, &old.der).await.1, old.der);
+
+    let new = SelfSigned::write(&dir);
+    let mut served = old.der.clone();
+    for _ in 0..50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        served = connect_tls(addr, &new.der).await.1;
+        if served == new.der {
+            break;
+        }
+    }
+
+    assert_eq!(served, new.der);
+    assert_eq!(get(&mut established, "/health/live").await, StatusCode::OK);
+    stop.cancel();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn rejects_mismatched_certificate_and_key() {
+    let dir = temp_dir("mismatch");
+    let first = SelfSigned::write(&dir);
+    let other = temp_dir("mismatch-other");
+    let second = SelfSigned::write(&other);
+
+    let err = tls::Certificates::load(&first.cert, &second.key).unwrap_err();
+
+    assert!(err.starts_with("Cannot use"), "{}", err);
+    assert!(tls::Certificates::load(&dir.join("missing.pem"), &first.key).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+    std::fs::remove_dir_all(other).unwrap();
+}
+
+#[tokio::test]
+async fn redirects_http_to_https() {
+    let redirect = routes::https_redirect(8443, &[]);
+
+    let res = request()
+        .method("POST")
+        .path("/v1/questions?limit=10&offset=20")
+        .header("host", "example.com:8080")
+        .reply(&redirect)
+        .await;
+
+    assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        res.headers()["location"],
+        "https://example.com:8443/v1/questions?limit=10&offset=20"
+    );
+
+    let res = request()
+        .path("/")
+        .header("host", "[::1]:80")
+        .reply(&routes::https_redirect(443, &[]))
+        .await;
+    assert_eq!(res.headers()["location"], "https://[::1]/");
+}
+
+#[tokio::test]
+async fn does_not_redirect_to_invalid_hosts() {
+    let redirect = routes::https_redirect(443, &[]);
+
+    let missing = request().path("/").reply(&redirect).await;
+    let invalid = request().path("/").header("host", "evil.com/@x").reply(&redirect).await;
+
+    assert_eq!(missing.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+    assert!(!invalid.headers().contains_key("location"));
+}
+
+#[tokio::test]
+async fn only_redirects_to_configured_server_names() {
+    let redirect = routes::https_redirect(443, &["api.example.com".to_string(), "::1".to_string()]);
+
+    let known = request().path("/").header("host", "API.example.com.:80").reply(&redirect).await;
+    let ipv6 = request().path("/").header("host", "[::1]").reply(&redirect).await;
+    let unknown = request().path("/").header("host", "evil.com").reply(&redirect).await;
+
+    assert_eq!(known.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(known.headers()["location"], "https://API.example.com./");
+    assert_eq!(ipv6.headers()["location"], "https://[::1]/");
+    assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+    assert!(!unknown.headers().contains_key("location"));
+}
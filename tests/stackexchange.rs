@@ -0,0 +1,104 @@
+mod support;
+
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+use sqlx::Row;
+
+use minimal_warp::stackexchange::{self, ImportError};
+use support::db::TestDb;
+
+const DUMP: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/stackexchange");
+
+#[tokio::test]
+async fn imports_questions_answers_and_accepted_answers() {
+    let db = TestDb::new().await;
+
+    let report = stackexchange::import_dump(&db.store, Path::new(DUMP), None)
+        .await
+        .unwrap();
+
+    assert_eq!((report.questions, report.answers), (1, 2));
+    assert_eq!(report.accepted_answers, 1);
+    // The tag wiki and the answer to an unknown question
+    assert_eq!(report.skipped_posts, 2);
+    assert_eq!(report.skipped_tags, 1);
+    assert_eq!((report.users, report.skipped_users), (2, 1));
+
+    let question = sqlx::query(
+        "SELECT title, content, tags, created_on, author, accepted_answer FROM questions",
+    )
+    .fetch_one(&db.store.connection)
+    .await
+    .unwrap();
+    assert_eq!(question.get::<String, _>("title"), "How do filters compose?");
+    assert_eq!(question.get::<String, _>("content"), "<p>With <code>and</code>?</p>");
+    assert_eq!(question.get::<Vec<String>, _>("tags"), vec!["rust", "warp"]);
+    assert_eq!(
+        question.get::<NaiveDateTime, _>("created_on"),
+        NaiveDateTime::parse_from_str("2008-07-31T21:42:52.667", "%Y-%m-%dT%H:%M:%S%.f").unwrap()
+    );
+    assert_eq!(question.get::<Option<String>, _>("author").as_deref(), Some("Alice"));
+
+    let answers: Vec<(i32, String, Option<String>)> =
+        sqlx::query_as("SELECT id, content, author FROM answers ORDER BY created_on")
+            .fetch_all(&db.store.connection)
+            .await
+            .unwrap();
+    assert_eq!(answers.len(), 2);
+    assert_eq!(answers[0].1, "<p>Use or.</p>");
+    assert_eq!(answers[0].2.as_deref(), Some("Bob"));
+    assert_eq!(answers[1].2.as_deref(), Some("Gone"));
+    assert_eq!(question.get::<Option<i32>, _>("accepted_answer"), Some(answers[1].0));
+}
+
+#[tokio::test]
+async fn resumes_an_interrupted_import() {
+    let db = TestDb::new().await;
+    stackexchange::import_dump(&db.store, Path::new(DUMP), None)
+        .await
+        .unwrap();
+    // Stopped before the accepted answer was stored
+    sqlx::query("UPDATE questions SET accepted_answer = NULL")
+        .execute(&db.store.connection)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM answers WHERE source_post_id = '3'")
+        .execute(&db.store.connection)
+        .await
+        .unwrap();
+
+    let report = stackexchange::import_dump(&db.store, Path::new(DUMP), None)
+        .await
+        .unwrap();
+
+    assert_eq!((report.questions, report.answers), (0, 1));
+    assert_eq!(report.already_imported, 2);
+    assert_eq!(report.accepted_answers, 1);
+    let (questions, answers): (i64, i64) = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM questions), (SELECT COUNT(*) FROM answers)",
+    )
+    .fetch_one(&db.store.connection)
+    .await
+    .unwrap();
+    assert_eq!((questions, answers), (1, 2));
+    let accepted: Option<String> = sqlx::query_scalar(
+        "SELECT a.source_post_id FROM questions q JOIN answers a ON a.id = q.accepted_answer",
+    )
+    .fetch_optional(&db.store.connection)
+    .await
+    .unwrap();
+    assert_eq!(accepted.as_deref(), Some("3"));
+}
+
+#[tokio::test]
+async fn requires_posts() {
+    let db = TestDb::new().await;
+    let empty = std::env::temp_dir().join(format!("minimal-warp-dump-{}", std::process::id()));
+    std::fs::create_dir_all(&empty).unwrap();
+
+    let result = stackexchange::import_dump(&db.store, &empty, None).await;
+    std::fs::remove_dir_all(&empty).unwrap();
+
+    assert!(matches!(result, Err(ImportError::Io(_))), "{:?}", result);
+}
@@ -0,0 +1,188 @@
+//! A stand-in for the APILayer `/bad_words` endpoint, listening on a
+//! random local port. Point the app at it with `--profanity-api-url`.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use minimal_warp::cli::{DegradedMode, ProfanityArgs};
+use serde_json::json;
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::reply::{self, Response};
+use warp::{Filter, Reply};
+
+pub const API_KEY: &str = "sj7Ik9TUYAUlhs6oMuGzK4ErlMbc8Ske";
+
+/// Settings for a profanity client talking to the mock at `url`: no
+/// retries, a short timeout and the circuit breaker's defaults
+pub fn profanity_args(url: String) -> ProfanityArgs {
+    ProfanityArgs {
+        profanity_api_url: url,
+        profanity_timeout: 500,
+        profanity_retries: 0,
+        profanity_cache_size: 100,
+        profanity_cache_ttl: 3600,
+        profanity_failure_threshold: 5,
+        profanity_cool_down: 30,
+        profanity_degraded_mode: DegradedMode::Reject,
+        profanity_policies: Vec::new(),
+    }
+}
+
+/// Words the mock censors, matched as whole words regardless of case
+const BAD_WORDS: &[&str] = &["damn", "shit"];
+
+/// How the mock answers `POST /bad_words`
+#[derive(Debug, Clone)]
+pub enum Behavior {
+    /// 200 with the text censored like APILayer does
+    Censor,
+    /// An error status with an `APIResponse` body, e.g. `{"message": "..."}`
+    Error(u16, &'static str),
+    /// An error status with a body that isn't JSON, as a proxy might send
+    RawError(u16, &'static str),
+    /// 200 with a body that doesn't parse as a censored text
+    Malformed,
+    /// Wait before answering like `Censor`
+    Slow(Duration),
+}
+
+/// A request the mock received
+#[derive(Debug, Clone)]
+pub struct Received {
+    pub query: String,
+    pub api_key: Option<String>,
+    pub request_id: Option<String>,
+    pub body: String,
+}
+
+#[derive(Clone)]
+pub struct MockApiLayer {
+    pub addr: SocketAddr,
+    behavior: Arc<Mutex<Behavior>>,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl MockApiLayer {
+    /// Start the mock on `127.0.0.1` with a port picked by the OS.
+    /// It runs until the test's runtime shuts down.
+    pub fn start(behavior: Behavior) -> Self {
+        let behavior = Arc::new(Mutex::new(behavior));
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let bad_words = {
+            let (behavior, received) = (behavior.clone(), received.clone());
+            warp::post()
+                .and(warp::path("bad_words"))
+                .and(warp::path::end())
+                .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+                .and(warp::header::optional::<String>("apikey"))
+                .and(warp::header::optional::<String>("x-request-id"))
+                .and(warp::body::bytes())
+                .then(move |query, api_key, request_id, body: Bytes| {
+                    let body = String::from_utf8_lossy(&body).into_owned();
+                    received.lock().unwrap().push(Received {
+                        query,
+                        api_key,
+                        request_id,
+                        body: body.clone(),
+                    });
+                    let behavior = behavior.lock().unwrap().clone();
+                    respond(behavior, body)
+                })
+        };
+        let root = warp::get().and(warp::path::end()).map(warp::reply);
+
+        let (addr, server) = warp::serve(bad_words.or(root)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        MockApiLayer {
+            addr,
+            behavior,
+            received,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn set_behavior(&self, behavior: Behavior) {
+        *self.behavior.lock().unwrap() = behavior;
+    }
+
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+
+    pub fn hits(&self) -> usize {
+        self.received.lock().unwrap().len()
+    }
+}
+
+async fn respond(behavior: Behavior, body: String) -> Response {
+    match behavior {
+        Behavior::Censor => censor(&body),
+        Behavior::Error(status, message) => reply::with_status(
+            reply::json(&json!({ "message": message })),
+            status_code(status),
+        )
+        .into_response(),
+        Behavior::RawError(status, body) => {
+            reply::with_status(body, status_code(status)).into_response()
+        }
+        Behavior::Malformed => reply::with_header(
+            r#"{"content": "unterminated"#,
+            "content-type",
+            "application/json",
+        )
+        .into_response(),
+        Behavior::Slow(delay) => {
+            tokio::time::sleep(delay).await;
+            censor(&body)
+        }
+    }
+}
+
+fn censor(content: &str) -> Response {
+    let mut censored = String::with_capacity(content.len());
+    let mut bad_words = Vec::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find(char::is_alphanumeric) {
+        censored.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len());
+        let word = &rest[..end];
+
+        if BAD_WORDS.contains(&word.to_lowercase().as_str()) {
+            censored.push_str(&"*".repeat(word.len()));
+            bad_words.push(json!({
+                "original": word,
+                "word": word.to_lowercase(),
+                "deviations": 0,
+                "info": 2,
+                "start": content.len() - rest.len(),
+                "end": content.len() - rest.len() + end,
+                "replacedLen": word.len(),
+            }));
+        } else {
+            censored.push_str(word);
+        }
+        rest = &rest[end..];
+    }
+    censored.push_str(rest);
+
+    reply::json(&json!({
+        "content": content,
+        "bad_words_total": bad_words.len(),
+        "bad_words_list": bad_words,
+        "censored_content": censored,
+    }))
+    .into_response()
+}
+
+fn status_code(status: u16) -> StatusCode {
+    StatusCode::from_u16(status).expect("invalid status code")
+}
@@ -0,0 +1,60 @@
+//! The application's filter tree wired to a test schema and a mock
+//! profanity API, for driving with `warp::test::request()`
+
+use clap::Parser;
+use warp::{Filter, Rejection, Reply};
+
+use minimal_warp::cli::ServeArgs;
+use minimal_warp::profanity::Profanity;
+use minimal_warp::routes;
+use minimal_warp::shutdown::Readiness;
+
+use super::apilayer::{profanity_args, Behavior, MockApiLayer};
+use super::db::TestDb;
+
+/// Token the test apps require for the `/admin` routes
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+
+#[derive(Parser)]
+struct Serve {
+    #[command(flatten)]
+    args: ServeArgs,
+}
+
+pub struct TestApp {
+    pub db: TestDb,
+    pub api: MockApiLayer,
+    pub profanity: Profanity,
+    pub readiness: Readiness,
+    /// The defaults of `serve`, except for rate limits and the admin token
+    pub args: ServeArgs,
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        let db = TestDb::new().await;
+        let api = MockApiLayer::start(Behavior::Censor);
+        let mut args = Serve::parse_from(["minimal-warp"]).args;
+        args.rate_limits.clear();
+        args.admin_token = Some(ADMIN_TOKEN.to_string());
+
+        TestApp {
+            profanity: Profanity::new(&profanity_args(api.url())),
+            readiness: Readiness::default(),
+            args,
+            db,
+            api,
+        }
+    }
+
+    /// The same routes `serve` answers with. Rate limit buckets are
+    /// kept by the returned filter, like within one server.
+    pub fn routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone + 'static {
+        routes::routes(
+            self.db.store.clone(),
+            self.profanity.clone(),
+            self.readiness.clone(),
+            &self.args,
+        )
+    }
+}
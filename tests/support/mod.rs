@@ -0,0 +1,5 @@
+#![allow(dead_code)]
+
+pub mod apilayer;
+pub mod app;
+pub mod db;